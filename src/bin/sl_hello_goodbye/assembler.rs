@@ -0,0 +1,269 @@
+//! assembles multi-line chat messages from individual log lines
+//!
+//! Every message in the chat log starts with a timestamp, lines without
+//! one are continuations of the previous message (multi-line chat, long
+//! notices,...). A message is complete as soon as the next line with a
+//! timestamp arrives or, for the last message written, once no further
+//! line arrived for a while.
+
+use chumsky::{
+    IterParser as _, Parser,
+    prelude::{any, just, one_of},
+};
+
+/// parses the timestamp at the start of a chat log line, ignoring the rest
+///
+/// this also accepts the unformatted timestamp format string some viewer
+/// versions wrote instead of a timestamp
+fn timestamp_prefix_parser<'src>()
+-> impl Parser<'src, &'src str, (), chumsky::extra::Err<chumsky::error::Rich<'src, char>>> {
+    let digits = |count| {
+        one_of("0123456789")
+            .repeated()
+            .exactly(count)
+            .collect::<String>()
+    };
+    just("[")
+        .then(digits(4))
+        .then(just("/"))
+        .then(digits(2))
+        .then(just("/"))
+        .then(digits(2))
+        .then(just(" "))
+        .then(digits(2))
+        .then(just(":"))
+        .then(digits(2))
+        .then(just(":").then(digits(2)).or_not())
+        .then(just("]"))
+        .ignored()
+        .or(just("[[year,datetime,slt]").ignored())
+        .then_ignore(any().repeated())
+}
+
+/// does this line start a new message (as opposed to continuing the
+/// previous one)
+fn starts_new_message(line: &str) -> bool {
+    timestamp_prefix_parser().parse(line).into_result().is_ok()
+}
+
+/// a complete, possibly multi-line, message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Message {
+    /// the lines of the message joined with newlines
+    pub(crate) text: String,
+    /// the position in the log file right after the message
+    pub(crate) end: crate::tailer::TailPosition,
+}
+
+/// collects continuation lines until the message they belong to is complete
+#[derive(Debug, Default)]
+pub(crate) struct MessageAssembler {
+    /// the message we are currently collecting lines for
+    pending: Option<Message>,
+}
+
+impl MessageAssembler {
+    /// add a line ending at the given position, returns the previous message
+    /// if this line starts a new one
+    pub(crate) fn push_line(
+        &mut self,
+        line: String,
+        end: crate::tailer::TailPosition,
+    ) -> Option<Message> {
+        match self.pending.take() {
+            Some(mut pending) if !starts_new_message(&line) => {
+                pending.text.push('\n');
+                pending.text.push_str(&line);
+                pending.end = end;
+                self.pending = Some(pending);
+                None
+            }
+            previous => {
+                self.pending = Some(Message { text: line, end });
+                previous
+            }
+        }
+    }
+
+    /// return the message collected so far, e.g. because no more lines
+    /// arrived for a while
+    pub(crate) const fn flush(&mut self) -> Option<Message> {
+        self.pending.take()
+    }
+
+    /// is there a message waiting for more lines or a flush
+    pub(crate) const fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+/// a complete message or a change to the log file as a whole
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LogEvent {
+    /// a complete, possibly multi-line, message
+    Message(Message),
+    /// all messages that were written to the log before we started have
+    /// been sent, all further messages are live
+    CaughtUp,
+    /// the log file was truncated
+    Truncated,
+    /// the log file was replaced by a new one
+    Rotated,
+}
+
+/// send an event, returns false if the receiver is gone
+async fn send(tx: &tokio::sync::mpsc::Sender<LogEvent>, event: LogEvent) -> bool {
+    if tx.send(event).await.is_err() {
+        tracing::debug!("Assembled message receiver is gone");
+        return false;
+    }
+    true
+}
+
+/// read tailed lines from one channel and send assembled messages to another
+///
+/// the last message is sent once no new line arrived for `idle_flush`,
+/// while no message is pending this only wakes up when lines arrive
+pub(crate) async fn assemble(
+    mut rx: tokio::sync::mpsc::Receiver<crate::tailer::TailEvent>,
+    tx: tokio::sync::mpsc::Sender<LogEvent>,
+    idle_flush: std::time::Duration,
+) {
+    let mut assembler = MessageAssembler::default();
+    loop {
+        let event = if assembler.is_pending() {
+            if let Ok(event) = tokio::time::timeout(idle_flush, rx.recv()).await {
+                event
+            } else {
+                if let Some(message) = assembler.flush()
+                    && !send(&tx, LogEvent::Message(message)).await
+                {
+                    return;
+                }
+                continue;
+            }
+        } else {
+            rx.recv().await
+        };
+        let (message, event) = match event {
+            Some(crate::tailer::TailEvent::Line { line, end }) => {
+                (assembler.push_line(line, end), None)
+            }
+            Some(crate::tailer::TailEvent::CaughtUp) => {
+                (assembler.flush(), Some(LogEvent::CaughtUp))
+            }
+            // a message never continues across files
+            Some(crate::tailer::TailEvent::Truncated) => {
+                (assembler.flush(), Some(LogEvent::Truncated))
+            }
+            Some(crate::tailer::TailEvent::Rotated) => (assembler.flush(), Some(LogEvent::Rotated)),
+            None => break,
+        };
+        for event in message.map(LogEvent::Message).into_iter().chain(event) {
+            if !send(&tx, event).await {
+                return;
+            }
+        }
+    }
+    if let Some(message) = assembler.flush() {
+        send(&tx, LogEvent::Message(message)).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// a position in the log file
+    const fn at(offset: u64) -> crate::tailer::TailPosition {
+        crate::tailer::TailPosition {
+            identity: None,
+            offset,
+        }
+    }
+
+    /// the text of a message ending at the given position
+    fn message(text: &str, offset: u64) -> Option<Message> {
+        Some(Message {
+            text: text.to_string(),
+            end: at(offset),
+        })
+    }
+
+    #[test]
+    fn test_assembler_continuation_lines() {
+        let mut assembler = MessageAssembler::default();
+        assert_eq!(
+            assembler.push_line("[2024/05/01 20:00]  Foo Bar: first".to_string(), at(1)),
+            None
+        );
+        assert_eq!(assembler.push_line("second line".to_string(), at(2)), None);
+        assert_eq!(assembler.push_line(String::new(), at(3)), None);
+        assert_eq!(
+            assembler.push_line(" [not a timestamp]".to_string(), at(4)),
+            None
+        );
+        assert_eq!(
+            assembler.push_line("[2024/05/01 20:00:05]  Baz: next".to_string(), at(5)),
+            message(
+                "[2024/05/01 20:00]  Foo Bar: first\nsecond line\n\n [not a timestamp]",
+                4
+            )
+        );
+        assert_eq!(
+            assembler.push_line(
+                "[[year,datetime,slt]/[mthnum,datetime,slt]/[day,datetime,slt] [hour,datetime,slt]:[min,datetime,slt]]  Qux: broken".to_string(),
+                at(6)
+            ),
+            message("[2024/05/01 20:00:05]  Baz: next", 5)
+        );
+        assert!(
+            assembler.is_pending(),
+            "last message should still be pending"
+        );
+        assert!(
+            assembler.flush().is_some(),
+            "flush should return the last message"
+        );
+        assert!(
+            !assembler.is_pending(),
+            "nothing should be pending after a flush"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_assemble_idle_flush() {
+        let (line_tx, line_rx) = tokio::sync::mpsc::channel(16);
+        let (message_tx, mut message_rx) = tokio::sync::mpsc::channel(16);
+        let join_handle = tokio::spawn(assemble(
+            line_rx,
+            message_tx,
+            std::time::Duration::from_millis(500),
+        ));
+        for (offset, line) in [
+            (1, "[2024/05/01 20:00]  Foo Bar: first"),
+            (2, "second line"),
+        ] {
+            assert!(
+                line_tx
+                    .send(crate::tailer::TailEvent::Line {
+                        line: line.to_string(),
+                        end: at(offset)
+                    })
+                    .await
+                    .is_ok(),
+                "send failed"
+            );
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(message_rx.try_recv().is_err(), "message flushed too early");
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        assert_eq!(
+            message_rx.try_recv().ok(),
+            message("[2024/05/01 20:00]  Foo Bar: first\nsecond line", 2).map(LogEvent::Message)
+        );
+        drop(line_tx);
+        assert!(join_handle.await.is_ok(), "assembler task failed");
+    }
+}
@@ -0,0 +1,663 @@
+//! the optional configuration file
+//!
+//! The configuration is read from a TOML file, e.g.
+//!
+//! ```toml
+//! [notifications]
+//! minimum_absence = "5s"
+//! areas = ["chat_range"]
+//!
+//! [[regions]]
+//! region = "Club X"
+//! minimum_absence = "30m"
+//! areas = ["chat_range", "draw_distance"]
+//! max_distance = 15.0
+//! arrival_grace = "20s"
+//!
+//! [[regions]]
+//! region = "My Home"
+//! enabled = false
+//!
+//! [early_warning]
+//! avatars = ["Bob Builder"]
+//! areas = ["draw_distance", "region"]
+//!
+//! [crowd]
+//! arrivals = 5
+//! window = "1m"
+//! arrival_window = "10s"
+//!
+//! [greetings]
+//! late_arrivals = "5s"
+//! emote_verbs = ["waves", "hugs", "greets", "nods to", "bows to"]
+//!
+//! [me]
+//! display_name = "Johnny"
+//! nicknames = ["JS"]
+//! first_name = false
+//!
+//! [team]
+//! members = ["Bob Builder"]
+//! annotate = false
+//!
+//! [mentions]
+//! enabled = true
+//! volumes = ["whisper", "say", "shout"]
+//! ```
+//!
+//! Settings a region rule does not mention are taken from the
+//! `[notifications]` table which also applies in all other regions and
+//! while we do not know which region we are in.
+
+/// an area an avatar can enter, as named in the configuration file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Area {
+    /// chat range
+    ChatRange,
+    /// draw distance
+    DrawDistance,
+    /// the region
+    Region,
+}
+
+impl From<Area> for sl_types::radar::Area {
+    fn from(value: Area) -> Self {
+        match value {
+            Area::ChatRange => Self::ChatRange,
+            Area::DrawDistance => Self::DrawDistance,
+            Area::Region => Self::Region,
+        }
+    }
+}
+
+/// when hello notifications are shown
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct NotificationPolicy {
+    /// whether hello notifications are shown at all
+    pub(crate) enabled: bool,
+    /// how long an avatar has to be gone before entering again reminds us
+    /// to greet them again
+    #[serde(with = "humantime_serde")]
+    pub(crate) minimum_absence: std::time::Duration,
+    /// which areas remind us to greet an avatar when they enter them
+    pub(crate) areas: Vec<Area>,
+    /// only remind us to greet avatars entering closer than this many
+    /// meters, e.g. to ignore people passing at the edge of chat range
+    pub(crate) max_distance: Option<sl_types::map::Distance>,
+    /// only remind us to greet avatars still around after this long or
+    /// once they talk, so people walking by are not reported
+    #[serde(with = "humantime_serde")]
+    pub(crate) arrival_grace: std::time::Duration,
+}
+
+impl Default for NotificationPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            minimum_absence: std::time::Duration::from_secs(5),
+            areas: vec![Area::ChatRange],
+            max_distance: None,
+            arrival_grace: std::time::Duration::ZERO,
+        }
+    }
+}
+
+impl NotificationPolicy {
+    /// does an avatar entering the given area remind us to greet them
+    pub(crate) fn triggers(&self, area: sl_types::radar::Area) -> bool {
+        self.enabled
+            && self
+                .areas
+                .iter()
+                .any(|trigger| sl_types::radar::Area::from(*trigger) == area)
+    }
+
+    /// is an avatar entering at the given distance close enough to remind
+    /// us to greet them, unknown distances always are
+    pub(crate) fn close_enough(&self, distance: Option<&sl_types::map::Distance>) -> bool {
+        match (distance, &self.max_distance) {
+            (Some(distance), Some(max_distance)) => distance <= max_distance,
+            _ => true,
+        }
+    }
+
+    /// does an avatar greeting us at the given volume from the given
+    /// distance remind us to greet them back, like arrivals only avatars
+    /// close enough do and shouts from further away than chat range never do
+    pub(crate) fn greets_back(
+        &self,
+        volume: sl_types::chat::ChatVolume,
+        distance: Option<&sl_types::map::Distance>,
+    ) -> bool {
+        self.enabled && volume <= sl_types::chat::ChatVolume::Say && self.close_enough(distance)
+    }
+}
+
+/// the notification policy for a single region, unset settings are taken
+/// from the general policy
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RegionPolicy {
+    /// the name of the region, compared case-insensitively
+    pub(crate) region: String,
+    /// whether hello notifications are shown at all in this region
+    #[serde(default)]
+    pub(crate) enabled: Option<bool>,
+    /// how long an avatar has to be gone before entering again reminds us
+    /// to greet them again
+    #[serde(default, with = "humantime_serde")]
+    pub(crate) minimum_absence: Option<std::time::Duration>,
+    /// which areas remind us to greet an avatar when they enter them
+    #[serde(default)]
+    pub(crate) areas: Option<Vec<Area>>,
+    /// only remind us to greet avatars entering closer than this many
+    /// meters
+    #[serde(default)]
+    pub(crate) max_distance: Option<sl_types::map::Distance>,
+    /// only remind us to greet avatars still around after this long or
+    /// once they talk
+    #[serde(default, with = "humantime_serde")]
+    pub(crate) arrival_grace: Option<std::time::Duration>,
+}
+
+/// low urgency notifications when certain avatars get close to chat range
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct EarlyWarning {
+    /// the legacy names of the avatars we want to know about early, e.g.
+    /// friends or VIPs, compared case-insensitively
+    pub(crate) avatars: Vec<String>,
+    /// which areas warn us about those avatars when they enter them
+    pub(crate) areas: Vec<Area>,
+}
+
+impl Default for EarlyWarning {
+    fn default() -> Self {
+        Self {
+            avatars: Vec::new(),
+            areas: vec![Area::DrawDistance, Area::Region],
+        }
+    }
+}
+
+impl EarlyWarning {
+    /// does the given avatar entering the given area warn us that they are
+    /// approaching
+    pub(crate) fn triggers(&self, name: &str, area: sl_types::radar::Area) -> bool {
+        self.avatars
+            .iter()
+            .any(|avatar| avatar.eq_ignore_ascii_case(name))
+            && self
+                .areas
+                .iter()
+                .any(|trigger| sl_types::radar::Area::from(*trigger) == area)
+    }
+}
+
+/// when many arrivals are collected in a single crowd notification
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Crowd {
+    /// how many arrivals within `window` still get their own notification,
+    /// further ones are collected in the crowd notification, 0 disables it
+    pub(crate) arrivals: usize,
+    /// the time window arrivals are counted in
+    #[serde(with = "humantime_serde")]
+    pub(crate) window: std::time::Duration,
+    /// for how long after we teleported or logged in arrivals are listed in
+    /// a single notification of the people who were already there, 0
+    /// disables it
+    #[serde(with = "humantime_serde")]
+    pub(crate) arrival_window: std::time::Duration,
+}
+
+impl Default for Crowd {
+    fn default() -> Self {
+        Self {
+            arrivals: 0,
+            window: std::time::Duration::from_secs(60),
+            arrival_window: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+/// how our own greetings are interpreted
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Greetings {
+    /// avatars that arrived less than this long before we greeted everyone
+    /// (e.g. "hi all") most likely arrived after we started typing it and
+    /// are not greeted by it, both times come from the chat log which
+    /// usually only has minute precision
+    #[serde(with = "humantime_serde")]
+    pub(crate) late_arrivals: std::time::Duration,
+    /// the verbs that make our emotes greetings of the avatars following
+    /// them, e.g. "/me waves at Alice"
+    pub(crate) emote_verbs: Vec<String>,
+}
+
+impl Default for Greetings {
+    fn default() -> Self {
+        Self {
+            late_arrivals: std::time::Duration::ZERO,
+            emote_verbs: ["waves", "hugs", "greets", "nods to", "bows to"]
+                .map(ToOwned::to_owned)
+                .to_vec(),
+        }
+    }
+}
+
+/// the names other avatars use for our own avatar besides its legacy name
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Me {
+    /// our display name, the log only shows legacy names
+    pub(crate) display_name: Option<String>,
+    /// nicknames people call us by
+    pub(crate) nicknames: Vec<String>,
+    /// whether the first name of our legacy name is one of our names too,
+    /// off by default since it is often someone else's name or a common
+    /// word as well
+    pub(crate) first_name: bool,
+}
+
+impl Me {
+    /// all lower case names we go by, our legacy name, its first name if
+    /// enabled, our display name and nicknames
+    pub(crate) fn names(&self, legacy_name: &str) -> Vec<String> {
+        let legacy_name = legacy_name.to_lowercase();
+        legacy_name
+            .split_whitespace()
+            .next()
+            .filter(|_| self.first_name)
+            .map(ToOwned::to_owned)
+            .into_iter()
+            .chain(
+                self.display_name
+                    .iter()
+                    .chain(&self.nicknames)
+                    .map(|name| name.to_lowercase()),
+            )
+            .chain([legacy_name.clone()])
+            .collect()
+    }
+
+    /// is the given name (e.g. from a greeting) one of ours, compared
+    /// case-insensitively
+    pub(crate) fn is_me(&self, legacy_name: &str, name: &str) -> bool {
+        let name = name.trim().to_lowercase();
+        self.names(legacy_name).contains(&name)
+    }
+}
+
+/// a chat volume, as named in the configuration file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Volume {
+    /// whisper
+    Whisper,
+    /// say
+    Say,
+    /// shout
+    Shout,
+    /// region say
+    RegionSay,
+}
+
+impl From<Volume> for sl_types::chat::ChatVolume {
+    fn from(value: Volume) -> Self {
+        match value {
+            Volume::Whisper => Self::Whisper,
+            Volume::Say => Self::Say,
+            Volume::Shout => Self::Shout,
+            Volume::RegionSay => Self::RegionSay,
+        }
+    }
+}
+
+/// notifications when someone mentions one of our names in local chat
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Mentions {
+    /// whether mentions produce notifications at all
+    pub(crate) enabled: bool,
+    /// which chat volumes mentioning us produce a notification
+    pub(crate) volumes: Vec<Volume>,
+}
+
+impl Default for Mentions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            volumes: vec![Volume::Whisper, Volume::Say, Volume::Shout],
+        }
+    }
+}
+
+impl Mentions {
+    /// does a mention at the given volume produce a notification
+    pub(crate) fn triggers(&self, volume: sl_types::chat::ChatVolume) -> bool {
+        self.enabled
+            && self
+                .volumes
+                .iter()
+                .any(|trigger| sl_types::chat::ChatVolume::from(*trigger) == volume)
+    }
+}
+
+/// the other hosts sharing greeting duty with us
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Team {
+    /// the legacy names of our teammates, compared case-insensitively
+    pub(crate) members: Vec<String>,
+    /// mark hello notifications as greeted by the teammate instead of
+    /// closing them
+    pub(crate) annotate: bool,
+}
+
+impl Team {
+    /// is the avatar with the given legacy name one of our teammates
+    pub(crate) fn is_member(&self, name: &str) -> bool {
+        self.members
+            .iter()
+            .any(|member| member.eq_ignore_ascii_case(name))
+    }
+}
+
+/// the contents of the configuration file
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// the notification policy outside the regions listed in `regions`
+    pub(crate) notifications: NotificationPolicy,
+    /// notification policies for specific regions
+    pub(crate) regions: Vec<RegionPolicy>,
+    /// which avatars we want to know about before they reach chat range
+    pub(crate) early_warning: EarlyWarning,
+    /// when many arrivals are collected in a single notification
+    pub(crate) crowd: Crowd,
+    /// how our own greetings are interpreted
+    pub(crate) greetings: Greetings,
+    /// the names other avatars use for us
+    pub(crate) me: Me,
+    /// whose greetings count as ours
+    pub(crate) team: Team,
+    /// when someone mentioning us produces a notification
+    pub(crate) mentions: Mentions,
+}
+
+impl Config {
+    /// read the configuration from the given file or, if there is none,
+    /// from the default location if a file exists there
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the file can not be read or parsed
+    pub(crate) fn load(path: Option<&std::path::Path>) -> Result<Self, crate::Error> {
+        let path = if let Some(path) = path {
+            path.to_owned()
+        } else {
+            let Some(config_dir) = dirs2::config_dir() else {
+                return Ok(Self::default());
+            };
+            let path = config_dir
+                .join(clap::crate_name!())
+                .join(crate::CONFIG_FILE_NAME);
+            if !path.exists() {
+                return Ok(Self::default());
+            }
+            path
+        };
+        tracing::debug!("Reading configuration from {}", path.display());
+        let contents = fs_err::read_to_string(&path).map_err(crate::Error::ConfigReadError)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// the notification policy in the given region or, if the region is not
+    /// known, the general one
+    pub(crate) fn policy(&self, region: Option<&str>) -> NotificationPolicy {
+        let mut policy = self.notifications.clone();
+        let Some(region) = region else {
+            return policy;
+        };
+        for rule in &self.regions {
+            if rule.region.eq_ignore_ascii_case(region) {
+                if let Some(enabled) = rule.enabled {
+                    policy.enabled = enabled;
+                }
+                if let Some(minimum_absence) = rule.minimum_absence {
+                    policy.minimum_absence = minimum_absence;
+                }
+                if let Some(areas) = &rule.areas {
+                    policy.areas.clone_from(areas);
+                }
+                if let Some(max_distance) = &rule.max_distance {
+                    policy.max_distance = Some(max_distance.clone());
+                }
+                if let Some(arrival_grace) = rule.arrival_grace {
+                    policy.arrival_grace = arrival_grace;
+                }
+            }
+        }
+        policy
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chumsky::Parser as _;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_region_policies() -> Result<(), crate::Error> {
+        let config: Config = toml::from_str(
+            r#"
+            [notifications]
+            minimum_absence = "1m"
+
+            [[regions]]
+            region = "Club X"
+            minimum_absence = "30m"
+            areas = ["chat_range", "draw_distance"]
+            max_distance = 15.0
+            arrival_grace = "20s"
+
+            [[regions]]
+            region = "My Home"
+            enabled = false
+            "#,
+        )?;
+        assert_eq!(
+            config.policy(None),
+            NotificationPolicy {
+                enabled: true,
+                minimum_absence: std::time::Duration::from_secs(60),
+                areas: vec![Area::ChatRange],
+                max_distance: None,
+                arrival_grace: std::time::Duration::ZERO,
+            }
+        );
+        let club = config.policy(Some("club x"));
+        assert_eq!(
+            club.minimum_absence,
+            std::time::Duration::from_secs(30 * 60)
+        );
+        assert_eq!(club.areas, [Area::ChatRange, Area::DrawDistance]);
+        assert_eq!(club.arrival_grace, std::time::Duration::from_secs(20));
+        let distance = |distance| {
+            sl_types::map::distance_parser()
+                .parse(distance)
+                .into_result()
+                .ok()
+        };
+        assert!(
+            club.close_enough(distance("3.5 m").as_ref()),
+            "3.5 m is close enough at the club"
+        );
+        assert!(
+            !club.close_enough(distance("19.8 m").as_ref()),
+            "19.8 m is too far away at the club"
+        );
+        assert!(
+            club.close_enough(None),
+            "unknown distances are always close enough"
+        );
+        assert!(
+            config
+                .policy(None)
+                .close_enough(distance("19.8 m").as_ref()),
+            "there is no distance limit outside the club"
+        );
+        assert!(
+            config
+                .policy(Some("Club X"))
+                .triggers(sl_types::radar::Area::DrawDistance),
+            "draw distance should trigger at the club"
+        );
+        assert!(
+            !config
+                .policy(Some("My Home"))
+                .triggers(sl_types::radar::Area::ChatRange),
+            "nothing should trigger at home"
+        );
+        assert_eq!(config.policy(Some("Sandbox")), config.policy(None));
+        Ok(())
+    }
+
+    #[test]
+    fn test_own_names() -> Result<(), crate::Error> {
+        let config: Config = toml::from_str(
+            r#"
+            [me]
+            display_name = "Johnny"
+            nicknames = ["JS"]
+            "#,
+        )?;
+        for name in ["John Smith", "johnny", "js "] {
+            assert!(config.me.is_me("John Smith", name), "{name} is us");
+        }
+        for name in ["john", "smith", "john smithers", "bob", ""] {
+            assert!(!config.me.is_me("John Smith", name), "{name} is not us");
+        }
+        let config: Config = toml::from_str(
+            r"
+            [me]
+            first_name = true
+            ",
+        )?;
+        assert!(
+            config.me.is_me("John Smith", "John"),
+            "the first name is ours once enabled"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mentions() -> Result<(), crate::Error> {
+        let config = Config::default();
+        assert!(
+            config
+                .mentions
+                .triggers(sl_types::chat::ChatVolume::Whisper),
+            "whispered mentions trigger by default"
+        );
+        assert!(
+            !config
+                .mentions
+                .triggers(sl_types::chat::ChatVolume::RegionSay),
+            "region say mentions do not trigger by default"
+        );
+        let config: Config = toml::from_str(
+            r#"
+            [mentions]
+            volumes = ["region_say"]
+            "#,
+        )?;
+        assert!(
+            config
+                .mentions
+                .triggers(sl_types::chat::ChatVolume::RegionSay),
+            "configured volumes replace the default ones"
+        );
+        assert!(!config.mentions.triggers(sl_types::chat::ChatVolume::Say));
+        Ok(())
+    }
+
+    #[test]
+    fn test_team() -> Result<(), crate::Error> {
+        let config: Config = toml::from_str(
+            r#"
+            [team]
+            members = ["Bob Builder"]
+            annotate = true
+            "#,
+        )?;
+        assert!(config.team.annotate);
+        assert!(config.team.is_member("bob builder"), "Bob is on the team");
+        assert!(!config.team.is_member("Bob"), "only legacy names match");
+        Ok(())
+    }
+
+    #[test]
+    fn test_greets_back() -> Result<(), crate::Error> {
+        let config: Config = toml::from_str(
+            r"
+            [notifications]
+            max_distance = 10.0
+            ",
+        )?;
+        let policy = config.policy(None);
+        let close = policy.max_distance.clone();
+        let far = close.clone().map(|distance| distance * 2u8);
+        assert!(
+            policy.greets_back(sl_types::chat::ChatVolume::Say, close.as_ref()),
+            "a greeting from close by should be returned"
+        );
+        assert!(
+            policy.greets_back(sl_types::chat::ChatVolume::Whisper, None),
+            "unknown distances are close enough"
+        );
+        assert!(
+            !policy.greets_back(sl_types::chat::ChatVolume::Say, far.as_ref()),
+            "a greeting from further away than max_distance is ignored"
+        );
+        assert!(
+            !policy.greets_back(sl_types::chat::ChatVolume::Shout, close.as_ref()),
+            "a shouted greeting is ignored"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_early_warning() -> Result<(), crate::Error> {
+        let config: Config = toml::from_str(
+            r#"
+            [early_warning]
+            avatars = ["Bob Builder"]
+            "#,
+        )?;
+        assert!(
+            config
+                .early_warning
+                .triggers("bob builder", sl_types::radar::Area::Region),
+            "entering the region should warn about Bob"
+        );
+        assert!(
+            !config
+                .early_warning
+                .triggers("Bob Builder", sl_types::radar::Area::ChatRange),
+            "chat range is handled by the hello notification"
+        );
+        assert!(
+            !config
+                .early_warning
+                .triggers("Alice Resident", sl_types::radar::Area::DrawDistance),
+            "Alice is not on the list"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,369 @@
+//! follows the IM and group chat logs next to the local chat log
+//!
+//! Firestorm writes every conversation to its own file in the avatar
+//! directory, named after the other avatar or, with a ` (group)` suffix,
+//! after the group. There can be hundreds of them so instead of a tailer
+//! with its own watcher per file a single watcher on the directory decides
+//! which file to read and only files that changed recently are kept open.
+//! A file that can not be read is closed and opened again on its next
+//! change instead of ending the whole task.
+
+use std::path::{Path, PathBuf};
+
+/// how long a conversation log is kept open after it last changed
+const CLOSE_IDLE_AFTER: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// a line written to a conversation log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConversationLine {
+    /// the file name of the conversation log
+    pub(crate) file_name: String,
+    /// the line without the line terminator
+    pub(crate) line: String,
+    /// the position in the log file right after the line
+    pub(crate) end: crate::tailer::TailPosition,
+}
+
+/// is the file with the given name in the avatar directory a conversation
+/// log (as opposed to the local chat log or other files)
+pub(crate) fn is_conversation_log(file_name: &str) -> bool {
+    Path::new(file_name)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("txt"))
+        && file_name != crate::LOCAL_CHAT_LOG_FILE_NAME
+        && !file_name.ends_with("-oldfile.txt")
+}
+
+/// the lower case name of the other avatar in the conversation logged to
+/// the file with the given name, group chats have none
+pub(crate) fn partner(file_name: &str) -> Option<String> {
+    let stem = file_name.strip_suffix(".txt").unwrap_or(file_name);
+    if stem.ends_with("(group)") {
+        return None;
+    }
+    Some(stem.replace(['_', '.'], " ").to_lowercase())
+}
+
+/// the file names of the conversation logs in the given directory
+///
+/// # Errors
+///
+/// returns an error if the directory can not be read
+pub(crate) fn conversation_logs(dir: &Path) -> Result<Vec<String>, crate::Error> {
+    let mut file_names = Vec::new();
+    for entry in fs_err::read_dir(dir).map_err(crate::Error::TailerIoError)? {
+        let entry = entry.map_err(crate::Error::TailerIoError)?;
+        if let Some(file_name) = entry.file_name().to_str()
+            && is_conversation_log(file_name)
+        {
+            file_names.push(file_name.to_owned());
+        }
+    }
+    file_names.sort();
+    Ok(file_names)
+}
+
+/// the conversation logs we follow
+#[derive(Debug)]
+struct Conversations {
+    /// the directory the logs are in
+    dir: PathBuf,
+    /// how far the logs that are not open yet were read, by file name,
+    /// logs without one were created after we started
+    positions: std::collections::BTreeMap<String, crate::tailer::TailPosition>,
+    /// the open logs and when they last changed by file name
+    tailers: std::collections::BTreeMap<String, (crate::tailer::Tailer, tokio::time::Instant)>,
+}
+
+impl Conversations {
+    /// read everything new in the conversation log with the given name
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the log can not be opened or read
+    fn poll(&mut self, file_name: &str) -> Result<Vec<ConversationLine>, crate::Error> {
+        let (tailer, last_changed) = match self.tailers.entry(file_name.to_owned()) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                let path = self.dir.join(file_name);
+                if !path.exists() {
+                    return Ok(Vec::new());
+                }
+                let tailer = match self.positions.get(file_name) {
+                    Some(position) => crate::tailer::Tailer::open_at(&path, *position)?,
+                    None => crate::tailer::Tailer::open_at_start(&path)?,
+                };
+                self.positions.remove(file_name);
+                entry.insert((tailer, tokio::time::Instant::now()))
+            }
+        };
+        *last_changed = tokio::time::Instant::now();
+        Ok(tailer
+            .poll()?
+            .into_iter()
+            .filter_map(|event| match event {
+                crate::tailer::TailEvent::Line { line, end } => Some(ConversationLine {
+                    file_name: file_name.to_owned(),
+                    line,
+                    end,
+                }),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// read everything new in the conversation log with the given name, on
+    /// errors the log is closed to be opened again on its next change
+    fn poll_or_close(&mut self, file_name: &str) -> Vec<ConversationLine> {
+        match self.poll(file_name) {
+            Ok(lines) => lines,
+            Err(err) => {
+                tracing::warn!("Error reading conversation log {file_name}, closing it: {err}");
+                self.close(file_name);
+                Vec::new()
+            }
+        }
+    }
+
+    /// close the conversation log with the given name, remembering how far
+    /// it was read
+    fn close(&mut self, file_name: &str) {
+        if let Some((tailer, _)) = self.tailers.remove(file_name) {
+            self.positions
+                .insert(file_name.to_owned(), tailer.position());
+        }
+    }
+
+    /// when the next open log is closed for being idle, if any are open
+    fn next_idle(&self) -> Option<tokio::time::Instant> {
+        self.tailers
+            .values()
+            .map(|(_, last_changed)| *last_changed + CLOSE_IDLE_AFTER)
+            .min()
+    }
+
+    /// close the logs that did not change for [`CLOSE_IDLE_AFTER`]
+    fn close_idle(&mut self) {
+        let now = tokio::time::Instant::now();
+        let idle = self
+            .tailers
+            .iter()
+            .filter(|(_, (_, last_changed))| *last_changed + CLOSE_IDLE_AFTER <= now)
+            .map(|(file_name, _)| file_name.to_owned())
+            .collect::<Vec<_>>();
+        for file_name in idle {
+            tracing::debug!("Closing idle conversation log {file_name}");
+            self.close(&file_name);
+        }
+    }
+}
+
+/// follow all conversation logs in the given directory, sending every new
+/// line to the given channel until the receiver goes away
+///
+/// logs with a position in `positions` are first read from there, other
+/// logs that already exist only from their current end, errors reading a
+/// single log are logged and only close that log
+///
+/// # Errors
+///
+/// returns an error if the directory can not be watched or read
+pub(crate) async fn follow(
+    dir: PathBuf,
+    positions: std::collections::BTreeMap<String, crate::tailer::TailPosition>,
+    tx: tokio::sync::mpsc::Sender<ConversationLine>,
+) -> Result<(), crate::Error> {
+    let (watch_tx, mut watch_rx) = tokio::sync::mpsc::channel(crate::CHANNEL_CAPACITY);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let file_names = match event {
+            // our own reads would otherwise wake us up again
+            Ok(event) if matches!(event.kind, notify::EventKind::Access(_)) => return,
+            Ok(event) => event
+                .paths
+                .iter()
+                .filter_map(|path| path.file_name()?.to_str().map(ToOwned::to_owned))
+                .filter(|file_name| is_conversation_log(file_name))
+                .map(Ok)
+                .collect::<Vec<_>>(),
+            Err(err) => vec![Err(err)],
+        };
+        for file_name in file_names {
+            if let Err(err) = watch_tx.blocking_send(file_name) {
+                tracing::debug!("Conversation log watch receiver is gone: {:?}", err);
+            }
+        }
+    })?;
+    notify::Watcher::watch(&mut watcher, &dir, notify::RecursiveMode::NonRecursive)?;
+    let mut conversations = Conversations {
+        dir,
+        positions: std::collections::BTreeMap::new(),
+        tailers: std::collections::BTreeMap::new(),
+    };
+    let mut changed = Vec::new();
+    for file_name in conversation_logs(&conversations.dir)? {
+        if let Some(position) = positions.get(&file_name) {
+            conversations.positions.insert(file_name.clone(), *position);
+            changed.push(file_name);
+        } else {
+            match crate::tailer::Tailer::open_at_end(&conversations.dir.join(&file_name)) {
+                Ok(tailer) => {
+                    conversations.positions.insert(file_name, tailer.position());
+                }
+                Err(err) => {
+                    tracing::warn!("Error opening conversation log {file_name}: {err}");
+                }
+            }
+        }
+    }
+    // catch up on the logs we read before, the file is only kept open if
+    // something was written to it while we were not running
+    for file_name in changed {
+        let lines = conversations.poll_or_close(&file_name);
+        if lines.is_empty() {
+            conversations.close(&file_name);
+            continue;
+        }
+        for line in lines {
+            if tx.send(line).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    loop {
+        let next_idle = conversations.next_idle();
+        let file_name = tokio::select! {
+            file_name = watch_rx.recv() => file_name,
+            () = tokio::time::sleep_until(next_idle.unwrap_or_else(tokio::time::Instant::now)),
+                if next_idle.is_some() =>
+            {
+                conversations.close_idle();
+                continue;
+            }
+        };
+        let Some(file_name) = file_name else {
+            break;
+        };
+        for line in conversations.poll_or_close(&file_name?) {
+            if tx.send(line).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    Err(crate::Error::FileWatcherClosed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_conversation_log_names() {
+        assert!(is_conversation_log("bob_builder.txt"));
+        assert!(is_conversation_log("Builders Guild (group).txt"));
+        assert!(!is_conversation_log("chat.txt"));
+        assert!(!is_conversation_log("chat-oldfile.txt"));
+        assert!(!is_conversation_log("settings_per_account.xml"));
+        assert_eq!(partner("bob_builder.txt"), Some("bob builder".to_string()));
+        assert_eq!(partner("Bob Builder.txt"), Some("bob builder".to_string()));
+        assert_eq!(partner("Builders Guild (group).txt"), None);
+    }
+
+    /// append a line to the conversation log with the given name
+    fn append(dir: &Path, file_name: &str, line: &str) -> Result<(), crate::Error> {
+        use std::io::Write as _;
+        fs_err::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(file_name))
+            .and_then(|mut file| writeln!(file, "{line}"))
+            .map_err(crate::Error::TailerIoError)
+    }
+
+    #[tokio::test]
+    async fn test_follow_conversations() -> Result<(), crate::Error> {
+        let temp_dir = crate::test_util::temp_dir()?;
+        let dir = temp_dir.path();
+        fs_err::write(dir.join("chat.txt"), "").map_err(crate::Error::TailerIoError)?;
+        append(
+            dir,
+            "alice_resident.txt",
+            "[2024/05/01 20:00]  Alice Resident: old",
+        )?;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(crate::CHANNEL_CAPACITY);
+        let join_handle = tokio::spawn(follow(
+            dir.to_path_buf(),
+            std::collections::BTreeMap::new(),
+            tx,
+        ));
+        // lines written before the watcher started are skipped, keep writing
+        // until one arrives
+        let mut line = None;
+        for _ in 0..50 {
+            append(dir, "bob_builder.txt", "[2024/05/01 20:01]  Me Myself: hi")?;
+            if let Ok(received) =
+                tokio::time::timeout(std::time::Duration::from_millis(100), rx.recv()).await
+            {
+                line = received;
+                break;
+            }
+        }
+        assert_eq!(
+            line.map(|line| (line.file_name, line.line)),
+            Some((
+                "bob_builder.txt".to_string(),
+                "[2024/05/01 20:01]  Me Myself: hi".to_string()
+            )),
+            "the line in the new log should be sent, the old line in the existing one not"
+        );
+        drop(rx);
+        join_handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_close_idle_conversations() -> Result<(), crate::Error> {
+        let temp_dir = crate::test_util::temp_dir()?;
+        let dir = temp_dir.path();
+        append(dir, "alice_resident.txt", "old")?;
+        let tailer = crate::tailer::Tailer::open_at_end(&dir.join("alice_resident.txt"))?;
+        let mut conversations = Conversations {
+            dir: dir.to_path_buf(),
+            positions: std::collections::BTreeMap::from([(
+                "alice_resident.txt".to_string(),
+                tailer.position(),
+            )]),
+            tailers: std::collections::BTreeMap::new(),
+        };
+        let poll = |conversations: &mut Conversations| {
+            conversations
+                .poll_or_close("alice_resident.txt")
+                .into_iter()
+                .map(|line| line.line)
+                .collect::<Vec<_>>()
+        };
+        append(dir, "alice_resident.txt", "first")?;
+        assert_eq!(poll(&mut conversations), ["first"]);
+        tokio::time::advance(CLOSE_IDLE_AFTER / 2).await;
+        conversations.close_idle();
+        assert_eq!(
+            conversations.tailers.len(),
+            1,
+            "recently changed logs stay open"
+        );
+        tokio::time::advance(CLOSE_IDLE_AFTER).await;
+        conversations.close_idle();
+        assert!(
+            conversations.tailers.is_empty(),
+            "idle logs should be closed"
+        );
+        assert_eq!(conversations.next_idle(), None);
+        append(dir, "alice_resident.txt", "second")?;
+        assert_eq!(
+            poll(&mut conversations),
+            ["second"],
+            "a closed log should be read on from where it was closed"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,617 @@
+//! recognizes greetings in chat and emotes and mentions of our names
+//!
+//! Messages are split into words, separators and sentence ends first so the
+//! words joining greeted names ("and", "und") only match whole words and
+//! punctuation or emoticons around the names are not mistaken for parts of
+//! them.
+
+use chumsky::{
+    IterParser as _, Parser,
+    prelude::{any, choice, just, one_of},
+};
+
+/// a token of a chat message
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// a word, may contain apostrophes, hyphens, underscores and inner dots
+    Word(String),
+    /// a comma or similar separating the names in a list
+    Separator,
+    /// the end of a sentence or an emoticon, ends a list of names
+    End,
+}
+
+/// the phrases starting a greeting, as words
+const GREETINGS: &[&[&str]] = &[
+    &["hi"],
+    &["hello"],
+    &["hallo"],
+    &["ahoy"],
+    &["wb"],
+    &["welcome", "back"],
+];
+
+/// words that may precede a greeting in a sentence, e.g. "oh hi john"
+const INTERJECTIONS: &[&str] = &["oh", "ah", "ooh", "well", "and", "so", "why"];
+
+/// words that may follow a greeting before the names, e.g. "hi again john"
+const FILLERS: &[&str] = &["there", "again", "back"];
+
+/// words that may follow a greeting verb in an emote before the names,
+/// e.g. "waves at alice"
+const PREPOSITIONS: &[&str] = &["at", "to"];
+
+/// words joining the last names of a list
+const CONJUNCTIONS: &[&str] = &["and", "und"];
+
+/// words that start a new clause instead of continuing a name, e.g. "hi
+/// john how are you"
+const CLAUSE_STARTS: &[&str] = &[
+    "how", "what", "who", "where", "when", "why", "good", "great", "glad", "welcome", "thanks",
+    "thank", "i", "i'm", "im", "you", "you're", "your", "we", "it", "it's", "is", "are", "was",
+    "there", "to", "lol", "xd", "hehe", "wie", "schön", "gut", "back", "from", "with",
+];
+
+/// words that usually start a new clause but are plausible names too, they
+/// only count as a name when they stand on their own at the start of the
+/// list or after a conjunction, e.g. "hi hope" but not "hi john hope you
+/// are well" or "hi nice to see you"
+const NAME_LIKE_CLAUSE_STARTS: &[&str] = &["hope", "nice", "long", "lange"];
+
+/// adverbs that end the list of names in an emote, e.g. "hugs bob tightly",
+/// in chat they are more likely to be names
+const EMOTE_ADVERBS: &[&str] = &["across", "tightly", "warmly", "happily", "gently"];
+
+/// is the word one of the given lower case words
+fn is_one_of(word: &str, words: &[&str]) -> bool {
+    words
+        .iter()
+        .any(|candidate| word.to_lowercase() == *candidate)
+}
+
+/// split a chat message into tokens, whitespace and other symbols are
+/// dropped
+fn token_parser<'src>()
+-> impl Parser<'src, &'src str, Vec<Token>, chumsky::extra::Err<chumsky::error::Rich<'src, char>>> {
+    let alphanumeric = any().filter(|c: &char| c.is_alphanumeric());
+    let word = alphanumeric
+        .then(
+            any()
+                .filter(|c: &char| c.is_alphanumeric() || matches!(c, '\'' | '’' | '_' | '-'))
+                .repeated(),
+        )
+        .to_slice()
+        .separated_by(just('.'))
+        .at_least(1)
+        .collect::<Vec<&str>>()
+        .map(|parts| Token::Word(parts.join(".")));
+    let mouth = one_of(")(][DdPpOo3|/\\*><}{@$SsXx");
+    let emoticon = choice((
+        one_of(":;=")
+            .then(choice((
+                one_of("-'^o").then(mouth).ignored(),
+                mouth.ignored(),
+            )))
+            .ignored(),
+        just("<3").ignored(),
+        just("^_^").ignored(),
+        just("^^").ignored(),
+        just("\\o/").ignored(),
+        just("\\o").ignored(),
+        just("o/").ignored(),
+    ))
+    .then_ignore(alphanumeric.not());
+    choice((
+        emoticon.to(Some(Token::End)),
+        word.map(Some),
+        one_of(",&+/").to(Some(Token::Separator)),
+        one_of(".!?;:\n").to(Some(Token::End)),
+        any().to(None),
+    ))
+    .repeated()
+    .collect::<Vec<_>>()
+    .map(|tokens| tokens.into_iter().flatten().collect())
+}
+
+/// do the tokens start with the words of the given phrase
+fn starts_with_phrase(tokens: &[Token], phrase: &[&str]) -> bool {
+    phrase.len() <= tokens.len()
+        && phrase.iter().zip(tokens).all(
+            |(expected, token)| matches!(token, Token::Word(word) if is_one_of(word, &[expected])),
+        )
+}
+
+/// the number of tokens taken up by the greeting phrase at the start of the
+/// tokens, if they start with one
+fn greeting_length(tokens: &[Token]) -> Option<usize> {
+    GREETINGS
+        .iter()
+        .find(|phrase| starts_with_phrase(tokens, phrase))
+        .map(|phrase| phrase.len())
+}
+
+/// does a word followed by the given token stand on its own instead of
+/// starting a longer name or clause, `adverbs` end the list of names too
+fn stands_alone(next: Option<&Token>, adverbs: &[&str]) -> bool {
+    match next {
+        Some(Token::Word(word)) => is_one_of(word, CONJUNCTIONS) || is_one_of(word, adverbs),
+        Some(Token::Separator | Token::End) | None => true,
+    }
+}
+
+/// the list of names at the start of the tokens following a greeting,
+/// `adverbs` are additional words ending the list
+fn greeted_list(tokens: &[Token], adverbs: &[&str]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut current = Vec::new();
+    let start = tokens
+        .iter()
+        .position(|token| !matches!(token, Token::Word(word) if is_one_of(word, FILLERS)))
+        .unwrap_or(tokens.len());
+    let tokens = tokens.get(start..).unwrap_or_default();
+    // whether the next word may be a name that looks like a clause start
+    let mut may_be_name = true;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Word(word) if is_one_of(word, CONJUNCTIONS) => {
+                names.push(std::mem::take(&mut current).join(" "));
+                may_be_name = true;
+            }
+            Token::Word(word) if is_one_of(word, CLAUSE_STARTS) || is_one_of(word, adverbs) => {
+                break;
+            }
+            Token::Word(word)
+                if is_one_of(word, NAME_LIKE_CLAUSE_STARTS)
+                    && !(may_be_name && stands_alone(tokens.get(index + 1), adverbs)) =>
+            {
+                break;
+            }
+            Token::Word(word) => {
+                current.push(word.as_str());
+                may_be_name = false;
+            }
+            Token::Separator => {
+                names.push(std::mem::take(&mut current).join(" "));
+                may_be_name = false;
+            }
+            Token::End => break,
+        }
+    }
+    names.push(current.join(" "));
+    names.retain(|name| !name.is_empty());
+    names
+}
+
+/// the tokens following each greeting phrase that starts a sentence
+fn greetings(tokens: &[Token]) -> impl Iterator<Item = &[Token]> {
+    tokens.iter().enumerate().filter_map(|(index, _)| {
+        let previous = index
+            .checked_sub(1)
+            .and_then(|previous| tokens.get(previous));
+        if previous.is_some_and(|previous| *previous != Token::End) {
+            return None;
+        }
+        let sentence = tokens.get(index..).unwrap_or_default();
+        let greeting = sentence
+            .iter()
+            .position(|token| match token {
+                Token::Word(word) => !is_one_of(word, INTERJECTIONS),
+                Token::Separator => false,
+                Token::End => true,
+            })
+            .and_then(|offset| sentence.get(offset..))
+            .unwrap_or_default();
+        greeting_length(greeting).map(|length| greeting.get(length..).unwrap_or_default())
+    })
+}
+
+/// the names greeted in the first sentence of the tokens that greets
+/// someone
+fn greeted_names(tokens: &[Token]) -> Option<Vec<String>> {
+    greetings(tokens)
+        .map(|names| greeted_list(names, &[]))
+        .find(|names| !names.is_empty())
+}
+
+/// does the message contain a greeting, with or without names, e.g. "hi!"
+/// in an IM where it can only be meant for the other avatar
+pub(crate) fn is_greeting(message: &str) -> bool {
+    token_parser()
+        .parse(message)
+        .into_result()
+        .is_ok_and(|tokens| greetings(&tokens).next().is_some())
+}
+
+/// parse a chat line as a welcome greeting and return the names of the greeted people
+///
+/// # Errors
+///
+/// returns an error if the parser fails
+pub(crate) fn welcome_greeting_parser<'src>()
+-> impl Parser<'src, &'src str, Vec<String>, chumsky::extra::Err<chumsky::error::Rich<'src, char>>>
+{
+    token_parser().try_map(|tokens, span| {
+        greeted_names(&tokens)
+            .ok_or_else(|| chumsky::error::Rich::custom(span, "no greeting found"))
+    })
+}
+
+/// the names greeted by the first of the given verb phrases in an emote
+/// that is followed by someone to greet
+fn emote_greeted_names(tokens: &[Token], verbs: &[Vec<&str>]) -> Option<Vec<String>> {
+    (0..tokens.len()).find_map(|index| {
+        let rest = tokens.get(index..).unwrap_or_default();
+        verbs.iter().find_map(|verb| {
+            if !starts_with_phrase(rest, verb) {
+                return None;
+            }
+            let mut names = rest.get(verb.len()..).unwrap_or_default();
+            while let Some((Token::Word(word), after)) = names.split_first()
+                && (is_one_of(word, FILLERS) || is_one_of(word, PREPOSITIONS))
+            {
+                names = after;
+            }
+            Some(greeted_list(names, EMOTE_ADVERBS)).filter(|names| !names.is_empty())
+        })
+    })
+}
+
+/// parse an emote as a greeting using one of the given verbs (e.g. "waves
+/// at alice" for the verb "waves") and return the names of the greeted
+/// people
+///
+/// # Errors
+///
+/// returns an error if the parser fails
+pub(crate) fn emote_greeting_parser<'src>(
+    verbs: &[String],
+) -> impl Parser<'src, &'src str, Vec<String>, chumsky::extra::Err<chumsky::error::Rich<'src, char>>>
++ use<'src> {
+    let verbs = verbs
+        .iter()
+        .map(|verb| verb.to_lowercase())
+        .collect::<Vec<_>>();
+    token_parser().try_map(move |tokens, span| {
+        let verbs = verbs
+            .iter()
+            .map(|verb| verb.split_whitespace().collect::<Vec<_>>())
+            .filter(|verb| !verb.is_empty())
+            .collect::<Vec<_>>();
+        emote_greeted_names(&tokens, &verbs)
+            .ok_or_else(|| chumsky::error::Rich::custom(span, "no greeting found"))
+    })
+}
+
+/// does the message mention one of the given names as whole words, a
+/// possessive ("john's") counts too
+pub(crate) fn mentions(message: &str, names: &[String]) -> bool {
+    let Ok(tokens) = token_parser().parse(message).into_result() else {
+        return false;
+    };
+    let names = names
+        .iter()
+        .map(|name| name.to_lowercase())
+        .collect::<Vec<_>>();
+    names.iter().any(|name| {
+        let words = name.split_whitespace().collect::<Vec<_>>();
+        !words.is_empty()
+            && (0..tokens.len()).any(|index| {
+                let rest = tokens.get(index..).unwrap_or_default();
+                words.len() <= rest.len()
+                    && words.iter().zip(rest).all(|(expected, token)| {
+                        matches!(token, Token::Word(word) if {
+                            let word = word.to_lowercase();
+                            word == *expected
+                                || word.strip_suffix("'s").or_else(|| word.strip_suffix("’s"))
+                                    == Some(expected)
+                        })
+                    })
+            })
+    })
+}
+
+/// does a greeted name (e.g. "bob" or "bob builder") address the avatar with
+/// the given legacy name, its words have to match a run of whole words of the
+/// legacy name except for the last one which may be shortened, e.g. "al"
+/// addresses Alice but not Sally or Donald
+pub(crate) fn addresses(legacy_name: &str, greeted: &str) -> bool {
+    let legacy_name = legacy_name.to_lowercase();
+    let name_words = legacy_name.split_whitespace().collect::<Vec<_>>();
+    let greeted = greeted.to_lowercase();
+    let greeted_words = greeted.split_whitespace().collect::<Vec<_>>();
+    let Some((last, leading)) = greeted_words.split_last() else {
+        return false;
+    };
+    name_words.windows(greeted_words.len()).any(|window| {
+        window
+            .split_last()
+            .is_some_and(|(last_word, leading_words)| {
+                leading_words == leading && last_word.starts_with(last)
+            })
+    })
+}
+
+/// is a greeted name one addressing everyone around us, e.g. "hi all"
+pub(crate) fn is_collective_addressee(greeted: &str) -> bool {
+    ["all", "everyone", "everybody", "folks", "alle", "y'all"]
+        .iter()
+        .any(|collective| greeted.trim().eq_ignore_ascii_case(collective))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ChumskyError, Error};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_welcome_greeting_parser_one_avatar() -> Result<(), Error> {
+        match welcome_greeting_parser().parse("hello john").into_result() {
+            Ok(parsed) => {
+                assert_eq!(parsed, ["john"]);
+            }
+            Err(e) => {
+                for err in &e {
+                    tracing::error!("{}", err);
+                }
+                return Err(crate::Error::ChatLogLineParseError(ChumskyError {
+                    description: "welcome greeting".to_string(),
+                    source: "hello john".to_string(),
+                    errors: e,
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_welcome_greeting_parser_two_avatars() -> Result<(), Error> {
+        match welcome_greeting_parser()
+            .parse("hello john and paul")
+            .into_result()
+        {
+            Ok(parsed) => {
+                assert_eq!(parsed, ["john", "paul"]);
+            }
+            Err(e) => {
+                for err in &e {
+                    tracing::error!("{}", err);
+                }
+                return Err(crate::Error::ChatLogLineParseError(ChumskyError {
+                    description: "welcome greeting two avatars".to_string(),
+                    source: "hello john and paul".to_string(),
+                    errors: e,
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_welcome_greeting_parser_three_avatars() -> Result<(), Error> {
+        match welcome_greeting_parser()
+            .parse("hello john, paul and mary")
+            .into_result()
+        {
+            Ok(parsed) => {
+                assert_eq!(parsed, ["john", "paul", "mary"]);
+            }
+            Err(e) => {
+                for err in &e {
+                    tracing::error!("{}", err);
+                }
+                return Err(crate::Error::ChatLogLineParseError(ChumskyError {
+                    description: "welcome greeting three avatars".to_string(),
+                    source: "hello john, paul and mary".to_string(),
+                    errors: e,
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_welcome_greeting_parser_table() {
+        let cases: &[(&str, Option<&[&str]>)] = &[
+            ("hello john", Some(&["john"])),
+            ("hi sandra", Some(&["sandra"])),
+            ("hello bundy", Some(&["bundy"])),
+            ("hi sandra and andy", Some(&["sandra", "andy"])),
+            ("hallo sandra und mundy", Some(&["sandra", "mundy"])),
+            ("hi john & paul", Some(&["john", "paul"])),
+            ("hi john/paul", Some(&["john", "paul"])),
+            (
+                "hello john, paul, and mary",
+                Some(&["john", "paul", "mary"]),
+            ),
+            ("hi john smith", Some(&["john smith"])),
+            ("hi john smith and mary", Some(&["john smith", "mary"])),
+            ("hello mary-jane", Some(&["mary-jane"])),
+            ("hi john.doe", Some(&["john.doe"])),
+            ("hi o'brien", Some(&["o'brien"])),
+            ("hi john.", Some(&["john"])),
+            ("hi john!! :)", Some(&["john"])),
+            ("hi john :d", Some(&["john"])),
+            ("hi john :-)", Some(&["john"])),
+            ("hi john ;p and paul", Some(&["john"])),
+            ("hi john <3", Some(&["john"])),
+            ("hi john ^^", Some(&["john"])),
+            ("hi john o/", Some(&["john"])),
+            ("hi john \\o/", Some(&["john"])),
+            ("hi john 😊", Some(&["john"])),
+            ("hi john:)", Some(&["john"])),
+            ("hi (john)", Some(&["john"])),
+            ("hello john, how are you?", Some(&["john"])),
+            ("hello john how are you", Some(&["john"])),
+            ("hi john, nice to see you", Some(&["john"])),
+            ("hi john and welcome", Some(&["john"])),
+            ("hi john\nhow are you", Some(&["john"])),
+            ("hi john lol", Some(&["john"])),
+            ("hi, john", Some(&["john"])),
+            ("oh hi john", Some(&["john"])),
+            ("oh, hi john", Some(&["john"])),
+            ("well hello john and paul!", Some(&["john", "paul"])),
+            ("lol. hi john", Some(&["john"])),
+            ("hi! hi john", Some(&["john"])),
+            ("hi again john", Some(&["john"])),
+            ("hi there john", Some(&["john"])),
+            ("wb john", Some(&["john"])),
+            ("welcome back john", Some(&["john"])),
+            ("welcome back, john and paul", Some(&["john", "paul"])),
+            ("ahoy john", Some(&["john"])),
+            ("Hello John", Some(&["John"])),
+            ("hi all", Some(&["all"])),
+            ("hello everyone!", Some(&["everyone"])),
+            ("hi y'all :)", Some(&["y'all"])),
+            ("hallo alle", Some(&["alle"])),
+            ("hi hope", Some(&["hope"])),
+            ("hi Nice!", Some(&["Nice"])),
+            ("hi john and hope", Some(&["john", "hope"])),
+            ("hello long, paul", Some(&["long", "paul"])),
+            ("hi tightly", Some(&["tightly"])),
+            ("hi john warmly", Some(&["john warmly"])),
+            ("hi john hope you are well", Some(&["john"])),
+            ("hi nice to see you", None),
+            ("hi john, nice", Some(&["john"])),
+            ("hi", None),
+            ("hi!", None),
+            ("hi there", None),
+            ("hi :)", None),
+            ("hiya john", None),
+            ("history is fun", None),
+            ("this is hilarious", None),
+            ("i said hi to john", None),
+            ("ohio is nice", None),
+            ("welcome john", None),
+            ("whistle while you work", None),
+            ("", None),
+        ];
+        for (message, expected) in cases {
+            let parsed = welcome_greeting_parser().parse(message).into_result().ok();
+            assert_eq!(
+                parsed,
+                expected.map(|names| names.iter().map(ToString::to_string).collect::<Vec<_>>()),
+                "parsing {message:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_emote_greeting_parser_table() {
+        let verbs = crate::config::Greetings::default().emote_verbs;
+        let cases: &[(&str, Option<&[&str]>)] = &[
+            ("waves at alice", Some(&["alice"])),
+            ("waves to alice and bob", Some(&["alice", "bob"])),
+            ("waves", None),
+            ("hugs bob", Some(&["bob"])),
+            ("hugs bob tightly", Some(&["bob"])),
+            ("hugs hope tightly", Some(&["hope"])),
+            ("waves at nice", Some(&["nice"])),
+            ("waves warmly", None),
+            (
+                "hugs bob builder, carol and dave :)",
+                Some(&["bob builder", "carol", "dave"]),
+            ),
+            ("greets everyone", Some(&["everyone"])),
+            ("nods to alice", Some(&["alice"])),
+            ("bows to alice.", Some(&["alice"])),
+            ("bows deeply", None),
+            ("smiles and waves at alice", Some(&["alice"])),
+            ("waves back at alice", Some(&["alice"])),
+            ("nods", None),
+            ("dances with alice", None),
+            ("hugsalot bob", None),
+            ("thanks alice for the hug", None),
+        ];
+        for (message, expected) in cases {
+            let parsed = emote_greeting_parser(&verbs)
+                .parse(message)
+                .into_result()
+                .ok();
+            assert_eq!(
+                parsed,
+                expected.map(|names| names.iter().map(ToString::to_string).collect::<Vec<_>>()),
+                "parsing {message:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_greeting() {
+        for message in ["hi", "Hello!", "oh hi :)", "brb. hi again", "hi bob"] {
+            assert!(is_greeting(message), "{message:?} is a greeting");
+        }
+        for message in ["history", "i said hi", "wave", ""] {
+            assert!(!is_greeting(message), "{message:?} is not a greeting");
+        }
+    }
+
+    #[test]
+    fn test_mentions() {
+        let names = [
+            "John Smith".to_string(),
+            "john".to_string(),
+            "JS".to_string(),
+        ];
+        for message in [
+            "is john around?",
+            "ask John Smith",
+            "that's john's hat",
+            "js: look at this",
+        ] {
+            assert!(mentions(message, &names), "{message:?} mentions us");
+        }
+        for message in ["johnny is here", "jsx", "smith", ""] {
+            assert!(
+                !mentions(message, &names),
+                "{message:?} does not mention us"
+            );
+        }
+    }
+
+    #[test]
+    fn test_addresses() {
+        for (legacy_name, greeted, expected) in [
+            ("Alice Resident", "al", true),
+            ("Sally Resident", "al", false),
+            ("Donald Duck", "al", false),
+            ("Fred Resident", "ed", false),
+            ("Ed Resident", "ed", true),
+            ("Joanna Resident", "ann", false),
+            ("Ann Resident", "ann", true),
+            ("Bob Builder", "Bob", true),
+            ("Bob Builder", "builder", true),
+            ("Bob Builder", "bob builder", true),
+            ("Bob Builder", "bob b", true),
+            ("Bob Builder", "builder bob", false),
+            ("Bob Builder", "bo builder", false),
+            ("Bob Builder", "bob builder jr", false),
+            ("Bob Builder", "", false),
+        ] {
+            assert_eq!(
+                addresses(legacy_name, greeted),
+                expected,
+                "does {greeted:?} address {legacy_name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_collective_addressees() {
+        for greeted in ["all", "Everyone", "everybody", "folks", "alle", "y'all"] {
+            assert!(
+                is_collective_addressee(greeted),
+                "{greeted} addresses everyone"
+            );
+        }
+        for greeted in ["sally", "allen", "bob"] {
+            assert!(
+                !is_collective_addressee(greeted),
+                "{greeted} is a single avatar"
+            );
+        }
+    }
+}
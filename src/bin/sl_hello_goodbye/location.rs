@@ -0,0 +1,160 @@
+//! tracks the region our own avatar is in
+//!
+//! The viewer does not log where we arrive, a completed teleport only
+//! mentions where it started and crossing into a neighbouring region is only
+//! mentioned if that region runs a different simulator version. The region
+//! we are in is therefore often only known once we leave it, avatars seen in
+//! the meantime are remembered so their last seen region can be recorded
+//! retroactively.
+
+/// a change in what we know about our own location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LocationChange {
+    /// we left the region we were in, everyone around us is gone
+    Left {
+        /// whether we teleported away (as opposed to crossing into a
+        /// neighbouring region)
+        teleport: bool,
+        /// the region we left, if known
+        region: Option<String>,
+        /// the lower case names of the avatars seen there while we did not
+        /// know which region we were in
+        seen: Vec<String>,
+    },
+    /// we found out which region we are in
+    Identified {
+        /// the region we are in
+        region: String,
+        /// the lower case names of the avatars seen here before we knew
+        /// which region we are in
+        seen: Vec<String>,
+    },
+}
+
+/// what we know about the region our own avatar is in
+#[derive(Debug, Default)]
+pub(crate) struct Location {
+    /// the region we are in, if known
+    region: Option<String>,
+    /// lower case names of the avatars seen since we arrived while the
+    /// region was unknown
+    seen: std::collections::BTreeSet<String>,
+}
+
+impl Location {
+    /// the region we are in, if known
+    pub(crate) fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    /// the viewer session ended, the next one can start anywhere
+    pub(crate) fn end_session(&mut self) {
+        self.region = None;
+        self.seen.clear();
+    }
+
+    /// update our location from a chat log line
+    pub(crate) fn apply(
+        &mut self,
+        line: &sl_chat_log_parser::ChatLogLine,
+    ) -> Option<LocationChange> {
+        match &line.event {
+            sl_chat_log_parser::ChatLogEvent::SystemMessage { message } => match message {
+                sl_chat_log_parser::system_messages::SystemMessage::TeleportCompleted {
+                    origin,
+                } => Some(self.left(true, Some(origin.region_name.to_string()))),
+                sl_chat_log_parser::system_messages::SystemMessage::SimulatorVersion {
+                    ..
+                } => Some(self.left(false, None)),
+                sl_chat_log_parser::system_messages::SystemMessage::RezObjectFailedDueToFullParcel {
+                    region_name,
+                    ..
+                }
+                | sl_chat_log_parser::system_messages::SystemMessage::PermissionToRezObjectDenied {
+                    region_name,
+                    ..
+                } if self.region.is_none() => {
+                    let region = region_name.to_string();
+                    self.region = Some(region.clone());
+                    Some(LocationChange::Identified {
+                        region,
+                        seen: std::mem::take(&mut self.seen).into_iter().collect(),
+                    })
+                }
+                _ => None,
+            },
+            sl_chat_log_parser::ChatLogEvent::AvatarLine { name, message } => {
+                let seen = match message {
+                    sl_chat_log_parser::avatar_messages::AvatarMessage::EnteredArea {
+                        area: sl_types::radar::Area::ChatRange,
+                        ..
+                    }
+                    | sl_chat_log_parser::avatar_messages::AvatarMessage::LeftArea {
+                        area: sl_types::radar::Area::ChatRange,
+                    } => true,
+                    sl_chat_log_parser::avatar_messages::AvatarMessage::Chat {
+                        volume, ..
+                    }
+                    | sl_chat_log_parser::avatar_messages::AvatarMessage::Emote {
+                        volume, ..
+                    } => *volume <= sl_types::chat::ChatVolume::Say,
+                    _ => false,
+                };
+                if seen && self.region.is_none() {
+                    self.seen.insert(name.to_lowercase());
+                }
+                None
+            }
+            sl_chat_log_parser::ChatLogEvent::OtherMessage { .. } => None,
+        }
+    }
+
+    /// we left the current region, the region we left may only be known
+    /// now
+    fn left(&mut self, teleport: bool, origin: Option<String>) -> LocationChange {
+        let region = self.region.take().or(origin);
+        LocationChange::Left {
+            teleport,
+            region,
+            seen: std::mem::take(&mut self.seen).into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chumsky::Parser as _;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_location_changes() {
+        let mut location = Location::default();
+        let mut changes = Vec::new();
+        for line in [
+            "[2024/05/01 20:00]  Second Life: Foo Bar entered chat range (5.2 m).",
+            "[2024/05/01 20:01]  Baz Qux: hello",
+            "[2024/05/01 20:03]  Second Life: Teleport completed from http://maps.secondlife.com/secondlife/Da%20Boom/128/128/25",
+            "[2024/05/01 20:04]  Second Life: Quux Resident entered chat range (3.0 m).",
+        ] {
+            let line = sl_chat_log_parser::chat_log_line_parser()
+                .parse(line)
+                .into_result();
+            assert!(line.is_ok(), "failed to parse {line:?}");
+            if let Ok(line) = line {
+                changes.extend(location.apply(&line));
+            }
+        }
+        assert_eq!(
+            changes,
+            [LocationChange::Left {
+                teleport: true,
+                region: Some("Da Boom".to_string()),
+                seen: vec!["baz qux".to_string(), "foo bar".to_string()],
+            }]
+        );
+        assert_eq!(location.region(), None);
+        location.end_session();
+        assert_eq!(location.seen.len(), 0, "a new session forgets who was seen");
+    }
+}
@@ -0,0 +1,1630 @@
+#![deny(unknown_lints)]
+#![deny(renamed_and_removed_lints)]
+#![forbid(unsafe_code)]
+#![deny(deprecated)]
+#![forbid(private_interfaces)]
+#![forbid(private_bounds)]
+#![forbid(non_fmt_panics)]
+#![deny(unreachable_code)]
+#![deny(unreachable_patterns)]
+#![forbid(unused_doc_comments)]
+#![forbid(unused_must_use)]
+#![deny(while_true)]
+#![deny(unused_parens)]
+#![deny(redundant_semicolons)]
+#![deny(non_ascii_idents)]
+#![deny(confusable_idents)]
+#![warn(missing_docs)]
+#![warn(clippy::missing_docs_in_private_items)]
+#![warn(clippy::cargo_common_metadata)]
+#![warn(rustdoc::missing_crate_level_docs)]
+#![deny(rustdoc::broken_intra_doc_links)]
+#![warn(missing_debug_implementations)]
+#![deny(clippy::mod_module_files)]
+//#![warn(clippy::pedantic)]
+#![warn(clippy::redundant_else)]
+#![warn(clippy::must_use_candidate)]
+#![warn(clippy::missing_panics_doc)]
+#![warn(clippy::missing_errors_doc)]
+#![warn(clippy::panic)]
+#![warn(clippy::unwrap_used)]
+#![warn(clippy::expect_used)]
+#![doc = include_str!("../../../README.md")]
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use tracing::instrument;
+use tracing_subscriber::{
+    EnvFilter, Layer as _, Registry, filter::LevelFilter, layer::SubscriberExt as _,
+    util::SubscriberInitExt as _,
+};
+
+use ariadne::{Color, Fmt as _, Label, Report, ReportKind, Source};
+use chumsky::Parser as _;
+
+mod assembler;
+mod config;
+mod conversations;
+mod greeting;
+mod location;
+mod notifications;
+mod presence;
+mod store;
+mod tailer;
+#[cfg(test)]
+mod test_util;
+
+/// describes the redb table to store the last seen time
+/// the key string is the avatar legacy name, the other one is
+/// the formatted time
+const LAST_SEEN_TABLE: redb::TableDefinition<String, String> =
+    redb::TableDefinition::new("last_seen");
+
+/// describes the redb table to store the region where each avatar was
+/// last seen, the key is the lower case avatar legacy name, avatars whose
+/// last seen region is unknown have no entry
+const LAST_SEEN_REGION_TABLE: redb::TableDefinition<String, String> =
+    redb::TableDefinition::new("last_seen_region");
+
+/// describes the redb table to store how far each tailed log file was
+/// processed, the key is the file name of the log file, the value the
+/// device and inode of the file (where available) and the byte offset
+const TAIL_POSITION_TABLE: redb::TableDefinition<String, (Option<(u64, u64)>, u64)> =
+    redb::TableDefinition::new("tail_position");
+
+/// describes the redb table to store the hello notifications currently
+/// shown, the key is the lower case avatar legacy name, the value the
+/// notification id, summary and body
+const PENDING_NOTIFICATION_TABLE: redb::TableDefinition<String, (u32, String, String)> =
+    redb::TableDefinition::new("pending_notification");
+
+/// the file name of the configuration file in our configuration directory
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// the file name of the local chat log in the avatar log directory
+const LOCAL_CHAT_LOG_FILE_NAME: &str = "chat.txt";
+
+/// format for the timestamps used in the last_seen.db
+const TIME_FORMAT: &[time::format_description::BorrowedFormatItem<'_>] =
+    time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+
+/// capacity of the channels between the tailer, the message assembler and
+/// the line handling, if a stage falls behind the previous one waits
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Error enum for the application
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// error reading environment variable
+    #[error("error when retrieving environment variable: {0}")]
+    EnvVarError(#[from] std::env::VarError),
+    /// error in clap
+    #[error("error in CLI option parsing: {0}")]
+    ClapError(#[from] clap::Error),
+    /// Could not determine database dir
+    #[error("Could not determine directory for database storage")]
+    CouldNotDetermineDatabaseStorageDir,
+    /// error parsing log filter
+    #[error("error parsing log filter: {0}")]
+    LogFilterParseError(#[from] tracing_subscriber::filter::ParseError),
+    /// error determining current user home directory
+    #[error("error determining current user home directory")]
+    HomeDirError,
+    /// local chat file not found
+    #[error("local chat file not found: {0}")]
+    LocalChatFileNotFound(std::path::PathBuf),
+    /// error reading the configuration file
+    #[error("error reading the configuration file: {0}")]
+    ConfigReadError(std::io::Error),
+    /// error parsing the configuration file
+    #[error("error parsing the configuration file: {0}")]
+    ConfigParseError(#[from] toml::de::Error),
+    /// error reading the tailed log file
+    #[error("error reading the tailed log file: {0}")]
+    TailerIoError(std::io::Error),
+    /// error watching the log directory for changes
+    #[error("error watching the log directory for changes: {0}")]
+    FileWatchError(#[from] notify::Error),
+    /// the file watcher stopped sending events
+    #[error("the file watcher stopped sending events")]
+    FileWatcherClosed,
+    /// error parsing chat log line
+    #[error("error parsing chat log line: {0}")]
+    ChatLogLineParseError(ChumskyError<chumsky::error::Rich<'static, char>>),
+    /// error joining the log reader task before shutdown
+    #[error("error joining the log reader task before shutdown: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+    /// redb database error
+    #[error("redb database error: {0}")]
+    DatabaseError(#[from] redb::DatabaseError),
+    /// redb transaction error
+    #[error("redb transaction error: {0}")]
+    TransactionError(#[from] redb::TransactionError),
+    /// redb table error
+    #[error("redb table error: {0}")]
+    TableError(#[from] redb::TableError),
+    /// redb storage error
+    #[error("redb storage error: {0}")]
+    StorageError(#[from] redb::StorageError),
+    /// redb commit error
+    #[error("redb storage error: {0}")]
+    CommitError(#[from] redb::CommitError),
+    /// redb error setting transaction durability
+    #[error("redb error setting transaction durability: {0}")]
+    SetDurabilityError(#[from] redb::SetDurabilityError),
+    /// the database writer task stopped
+    #[error("the database writer task stopped, see the error it logged")]
+    DbWriterStopped,
+    /// error installing the signal handlers for shutdown
+    #[error("error installing the signal handlers for shutdown: {0}")]
+    SignalError(std::io::Error),
+    /// error formatting time
+    #[error("error formatting time: {0}")]
+    TimeFormatError(#[from] time::error::Format),
+    /// error parsing time
+    #[error("error parsing time: {0}")]
+    TimeParseError(#[from] time::error::Parse),
+    /// error creating directory for database
+    #[error("error creating directory for database: {0}")]
+    CreateDbDirError(std::io::Error),
+}
+
+/// The Clap type for all the commandline parameters
+#[derive(clap::Parser, Debug)]
+#[clap(name = clap::crate_name!(),
+       about = clap::crate_description!(),
+       author = clap::crate_authors!(),
+       version = clap::crate_version!(),
+       )]
+struct Options {
+    /// name of the logged in avatar whose chat.txt log file to watch (not display name);
+    /// may carry a Firestorm grid suffix (e.g. "Foo Bar.second_life_beta") to select the
+    /// per-grid log directory and greeting state
+    #[clap(long)]
+    avatar_name: String,
+    /// configuration file with the notification policies, defaults to
+    /// config.toml in the sl-hello-goodbye configuration directory if that
+    /// exists
+    #[clap(long)]
+    config: Option<std::path::PathBuf>,
+    /// how long to wait for continuation lines of the last message written to
+    /// the log before processing it
+    #[clap(long, default_value = "500ms", value_parser = humantime::parse_duration)]
+    multi_line_flush_delay: std::time::Duration,
+    /// how long to collect last seen updates before committing them to the
+    /// database in a single transaction, 0s commits every update immediately
+    #[clap(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    db_commit_interval: std::time::Duration,
+    /// whether batched commits are synced to disk immediately or only on
+    /// shutdown
+    #[clap(long, value_enum, default_value = "immediate")]
+    db_durability: store::CommitDurability,
+    /// start at the end of the local chat log instead of catching up on lines
+    /// written since the last run
+    #[clap(long)]
+    no_resume: bool,
+    /// show notifications for lines written since the last run while catching
+    /// up on them, by default they only update the last seen times
+    #[clap(long)]
+    catch_up_notifications: bool,
+    /// on startup show notifications for everyone who is in chat range
+    /// according to the local chat log and was not greeted yet, once caught
+    /// up on the lines written since the last run
+    #[clap(long)]
+    restore_notifications: bool,
+    /// treat a gap of at least this long between two lines in the local chat
+    /// log as the end of one viewer session and the start of the next, e.g.
+    /// when the viewer crashed without a new login being logged, by default
+    /// (0s) only log rotation and login messages start new sessions since
+    /// being away from the keyboard for a long time looks the same
+    #[clap(long, default_value = "0s", value_parser = humantime::parse_duration)]
+    session_idle_gap: std::time::Duration,
+    /// how long an avatar that left chat range is still considered present,
+    /// its notification stays open and returning continues its visit
+    #[clap(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    leave_grace_period: std::time::Duration,
+    /// how long after the leave grace period an avatar returning to chat
+    /// range still continues its previous visit instead of being greeted
+    /// again
+    #[clap(long, default_value = "2m", value_parser = humantime::parse_duration)]
+    reentry_grace_period: std::time::Duration,
+}
+
+/// a wrapped error in case parsing fails to get proper error output
+/// the chumsky errors themselves lack Display and std::error::Error
+/// implementations
+#[derive(Debug)]
+pub struct ChumskyError<E> {
+    /// description of the object we were trying to parse
+    pub description: String,
+    /// source string for parsing
+    pub source: String,
+    /// errors encountered during parsing
+    pub errors: Vec<E>,
+}
+
+impl std::fmt::Display for ChumskyError<chumsky::error::Rich<'static, char>> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for e in &self.errors {
+            let msg = format!(
+                "While parsing {}: {}{}, expected {}",
+                self.description,
+                if e.found().is_some() {
+                    "Unexpected token"
+                } else {
+                    "Unexpected end of input"
+                },
+                format_args!(" while parsing {:?}", e.contexts().collect::<Vec<_>>()),
+                if e.expected().len() == 0 {
+                    "end of input".to_string()
+                } else {
+                    e.expected()
+                        .map(|rich_pattern| rich_pattern.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+            );
+
+            let report = Report::build(ReportKind::Error, e.span().start..e.span().end)
+                .with_code(3)
+                .with_message(msg)
+                .with_label(
+                    Label::new(e.span().start..e.span().end)
+                        .with_message(format!(
+                            "Unexpected {}",
+                            e.found().map_or_else(
+                                || "end of input".to_string(),
+                                |c| format!("token {}", c.fg(Color::Red))
+                            )
+                        ))
+                        .with_color(Color::Red),
+                );
+
+            let report = match e.reason() {
+                chumsky::error::RichReason::ExpectedFound {
+                    expected: _,
+                    found: _,
+                } => report,
+                chumsky::error::RichReason::Custom(msg) => report.with_label(
+                    Label::new(e.span().start..e.span().end)
+                        .with_message(format!("{}", msg.fg(Color::Yellow)))
+                        .with_color(Color::Yellow),
+                ),
+            };
+
+            let mut s: Vec<u8> = Vec::new();
+            report
+                .finish()
+                .write(Source::from(&self.source), &mut s)
+                .map_err(|_err| <std::fmt::Error as std::default::Default>::default())?;
+            let s = std::str::from_utf8(&s)
+                .map_err(|_err| <std::fmt::Error as std::default::Default>::default())?;
+            write!(f, "{s}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> std::error::Error for ChumskyError<E>
+where
+    E: std::fmt::Debug,
+    Self: std::fmt::Display,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+/// determine avatar log dir from avatar name
+fn avatar_log_dir(avatar_name: &str) -> Result<PathBuf, crate::Error> {
+    let avatar_dir_name = avatar_name.replace(' ', "_").to_lowercase();
+    tracing::debug!("Avatar dir name: {}", avatar_dir_name);
+
+    let Some(home_dir) = dirs2::home_dir() else {
+        tracing::error!("Could not determine current user home directory");
+        return Err(crate::Error::HomeDirError);
+    };
+
+    Ok(home_dir.join(".firestorm/").join(avatar_dir_name))
+}
+
+/// The main behaviour of the binary should go here
+#[instrument]
+async fn do_stuff() -> Result<(), crate::Error> {
+    let options = <Options as clap::Parser>::parse();
+    tracing::debug!("{:#?}", options);
+    let config = config::Config::load(options.config.as_deref())?;
+    tracing::debug!("{:#?}", config);
+
+    // The instance name passed in may carry a grid suffix (e.g. ".second_life_beta") so
+    // that per-grid log directories and greeting state stay separate. An avatar's chat-log
+    // name never contains a '.', so the part before the first '.' is the clean avatar name
+    // used for self-greeting matching.
+    let clean_avatar_name = options
+        .avatar_name
+        .split('.')
+        .next()
+        .unwrap_or(&options.avatar_name)
+        .to_string();
+
+    let Some(db_path) = dirs2::config_dir() else {
+        return Err(crate::Error::CouldNotDetermineDatabaseStorageDir);
+    };
+    let db_path = db_path.join(clap::crate_name!());
+    let db_path = db_path.join(&options.avatar_name);
+    fs_err::create_dir_all(&db_path).map_err(crate::Error::CreateDbDirError)?;
+
+    let db = std::sync::Arc::new(redb::Database::create(db_path.join("last_seen.redb"))?);
+
+    let avatar_dir = avatar_log_dir(&options.avatar_name)?;
+
+    let local_chat_log_file = avatar_dir.join(LOCAL_CHAT_LOG_FILE_NAME);
+
+    if !local_chat_log_file.exists() {
+        tracing::error!(
+            "Local chat log {} does not exist for this avatar",
+            local_chat_log_file.display()
+        );
+        return Err(crate::Error::LocalChatFileNotFound(local_chat_log_file));
+    }
+
+    let (tail_tx, tail_rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+    let (tx, mut rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+
+    let start_position = if options.no_resume {
+        None
+    } else {
+        store::read_tail_position(&db, LOCAL_CHAT_LOG_FILE_NAME)?
+    };
+    // only lines written while we were not running need catching up on
+    let mut catching_up = start_position.is_some();
+    // lines processed by a previous run (or all existing ones if there was
+    // none) are only used to find out who is in chat range right now
+    let session_idle_gap = Some(options.session_idle_gap).filter(|gap| !gap.is_zero());
+    let (mut presence, start_position) = presence::reconstruct(
+        &local_chat_log_file,
+        &clean_avatar_name,
+        start_position,
+        session_idle_gap,
+        config.greetings.clone(),
+    )?;
+    // the log rarely says which region we are in, region rules silently not
+    // applying would be confusing
+    if !config.regions.is_empty() && presence.region().is_none() {
+        tracing::warn!(
+            "Region rules are configured but the chat log does not show which region we are in, the [notifications] policy applies until it does"
+        );
+    }
+    // lines written while we were not running are processed much faster
+    // than they were written so timers running on the wall clock would span
+    // far more of the log than intended, they only start once caught up
+    let grace_periods = presence::GracePeriods {
+        leave: options.leave_grace_period,
+        reentry: options.reentry_grace_period,
+    };
+    let replay_crowd = config::Crowd {
+        arrivals: 0,
+        arrival_window: std::time::Duration::ZERO,
+        ..config.crowd.clone()
+    };
+    if !catching_up {
+        presence.set_grace_periods(grace_periods);
+    }
+
+    let tail_join_handle = tokio::spawn(tailer::tail(
+        local_chat_log_file,
+        Some(start_position),
+        tail_tx,
+    ));
+
+    let mut conversation_positions = BTreeMap::new();
+    if !options.no_resume {
+        for file_name in conversations::conversation_logs(&avatar_dir)? {
+            if let Some(position) = store::read_tail_position(&db, &file_name)? {
+                conversation_positions.insert(file_name, position);
+            }
+        }
+    }
+    let (conversation_tx, mut conversation_rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+    let conversations_join_handle = tokio::spawn(async move {
+        // greetings in conversations are a nice to have, local chat keeps
+        // working without them
+        if let Err(err) =
+            conversations::follow(avatar_dir, conversation_positions, conversation_tx).await
+        {
+            tracing::error!("Stopped following conversation logs: {err}");
+        }
+    });
+
+    let join_handle = tokio::spawn(assembler::assemble(
+        tail_rx,
+        tx,
+        options.multi_line_flush_delay,
+    ));
+
+    let (db_writer, db_join_handle) = store::DbWriter::spawn(
+        std::sync::Arc::clone(&db),
+        options.db_commit_interval,
+        options.db_durability,
+    );
+
+    let mut notifications = notifications::HelloNotifications::new(
+        db_writer.clone(),
+        if catching_up {
+            replay_crowd
+        } else {
+            config.crowd.clone()
+        },
+        Box::new(notifications::DesktopNotifier::default()),
+    );
+    // who is in chat range right now is only known once we caught up on the
+    // lines written while we were not running
+    let mut pending_notifications = store::read_pending_notifications(&db)?;
+    if !catching_up {
+        restore_notifications(
+            std::mem::take(&mut pending_notifications),
+            &presence,
+            &mut notifications,
+            options.restore_notifications && config.policy(presence.region()).enabled,
+        )
+        .await?;
+    }
+    let mut last_seen_in_chat_range = store::read_last_seen(&db)?;
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    // the timestamp of the last line we processed, i.e. the latest time we
+    // know the current viewer session was still going
+    let mut last_timestamp: Option<time::PrimitiveDateTime> = None;
+
+    loop {
+        let next_expiry = presence.next_expiry();
+        let next_due = notifications.next_due();
+        let event = tokio::select! {
+            result = &mut shutdown => {
+                result?;
+                tracing::info!("Received shutdown signal");
+                break;
+            }
+            () = tokio::time::sleep_until(next_expiry.unwrap_or_else(tokio::time::Instant::now)),
+                if next_expiry.is_some() =>
+            {
+                for avatar in presence.expire() {
+                    tracing::debug!("{} stayed out of chat range, closing notification", avatar.name);
+                    notifications.close(&avatar.name).await?;
+                }
+                continue;
+            }
+            () = tokio::time::sleep_until(next_due.unwrap_or_else(tokio::time::Instant::now)),
+                if next_due.is_some() =>
+            {
+                notifications.show_due().await?;
+                continue;
+            }
+            Some(conversation_line) = conversation_rx.recv() => {
+                conversation_greeting(
+                    &conversation_line,
+                    &clean_avatar_name,
+                    &config.greetings,
+                    &mut presence,
+                    &mut notifications,
+                )
+                .await?;
+                db_writer
+                    .tail_position(&conversation_line.file_name, conversation_line.end)
+                    .await?;
+                continue;
+            }
+            event = rx.recv() => event,
+        };
+        let Some(event) = event else {
+            break;
+        };
+        let (line, end) = match event {
+            assembler::LogEvent::Message(message) => (message.text, message.end),
+            assembler::LogEvent::CaughtUp => {
+                if catching_up {
+                    tracing::info!("Caught up on lines written since the last run");
+                    presence.set_grace_periods(grace_periods);
+                    notifications.set_crowd(config.crowd.clone());
+                    restore_notifications(
+                        std::mem::take(&mut pending_notifications),
+                        &presence,
+                        &mut notifications,
+                        options.restore_notifications && config.policy(presence.region()).enabled,
+                    )
+                    .await?;
+                }
+                catching_up = false;
+                continue;
+            }
+            assembler::LogEvent::Truncated => {
+                tracing::info!("Local chat log was truncated");
+                end_presence(
+                    "log truncated",
+                    last_timestamp,
+                    presence.region().map(ToOwned::to_owned),
+                    presence.end_session(),
+                    &mut notifications,
+                    &db_writer,
+                    &mut last_seen_in_chat_range,
+                )
+                .await?;
+                continue;
+            }
+            assembler::LogEvent::Rotated => {
+                tracing::info!("Local chat log was rotated");
+                end_presence(
+                    "log rotated",
+                    last_timestamp,
+                    presence.region().map(ToOwned::to_owned),
+                    presence.end_session(),
+                    &mut notifications,
+                    &db_writer,
+                    &mut last_seen_in_chat_range,
+                )
+                .await?;
+                notifications.arrived();
+                continue;
+            }
+        };
+        println!("parsing line:\n{line}");
+        let parsed_line = sl_chat_log_parser::chat_log_line_parser()
+            .parse(&line)
+            .into_result();
+        println!("parse result:\n{parsed_line:#?}");
+
+        if let Ok(ref parsed_line) = parsed_line {
+            if presence::starts_session(parsed_line, last_timestamp, session_idle_gap) {
+                end_presence(
+                    "new session started",
+                    last_timestamp,
+                    presence.region().map(ToOwned::to_owned),
+                    presence.end_session(),
+                    &mut notifications,
+                    &db_writer,
+                    &mut last_seen_in_chat_range,
+                )
+                .await?;
+                notifications.arrived();
+            }
+            match presence.apply(parsed_line, &clean_avatar_name) {
+                Some(location::LocationChange::Left {
+                    teleport,
+                    region,
+                    seen,
+                }) => {
+                    end_presence(
+                        if teleport {
+                            "teleported away"
+                        } else {
+                            "changed region"
+                        },
+                        parsed_line.timestamp.or(last_timestamp),
+                        region.clone(),
+                        presence.leave(),
+                        &mut notifications,
+                        &db_writer,
+                        &mut last_seen_in_chat_range,
+                    )
+                    .await?;
+                    if teleport {
+                        notifications.arrived();
+                    }
+                    if let Some(region) = region {
+                        tag_last_seen_region(
+                            &region,
+                            seen,
+                            &db_writer,
+                            &mut last_seen_in_chat_range,
+                        )
+                        .await?;
+                    }
+                }
+                Some(location::LocationChange::Identified { region, seen }) => {
+                    tracing::info!("We are in region {region}");
+                    tag_last_seen_region(&region, seen, &db_writer, &mut last_seen_in_chat_range)
+                        .await?;
+                }
+                None => {}
+            }
+            last_timestamp = parsed_line.timestamp.or(last_timestamp);
+        }
+
+        if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp,
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    ref name,
+                    message:
+                        sl_chat_log_parser::avatar_messages::AvatarMessage::EnteredArea {
+                            area,
+                            ref distance,
+                        },
+                },
+        }) = parsed_line
+        {
+            let policy = config.policy(presence.region());
+            let (last_seen_description, last_seen_age) = if let Some(store::LastSeen {
+                timestamp: last_seen_timestamp,
+                region: last_seen_region,
+            }) =
+                last_seen_in_chat_range.get(&name.to_lowercase())
+            {
+                let last_seen_at = last_seen_region
+                    .as_ref()
+                    .map(|region| format!(" at {region}"))
+                    .unwrap_or_default();
+                if let Some(timestamp) = timestamp {
+                    let last_seen_age = timestamp - *last_seen_timestamp;
+                    if let Ok(std_last_seen_age) = last_seen_age.try_into() {
+                        (
+                            format!(
+                                "Last seen {} ago{last_seen_at} ({})",
+                                <humantime::Duration as From<std::time::Duration>>::from(
+                                    std_last_seen_age
+                                ),
+                                last_seen_timestamp
+                            ),
+                            Some(last_seen_age),
+                        )
+                    } else {
+                        (
+                            format!(
+                                "Could not convert last seen age to humantime: {last_seen_age}",
+                            ),
+                            Some(last_seen_age),
+                        )
+                    }
+                } else {
+                    (
+                        "Unable to determine timestamp for current message".to_string(),
+                        None,
+                    )
+                }
+            } else {
+                ("Not seen recently".to_string(), None)
+            };
+            let returned = area == sl_types::radar::Area::ChatRange
+                && returned_to_chat_range(name, &presence, &mut notifications);
+            let due = (!catching_up || options.catch_up_notifications)
+                && !returned
+                && (last_seen_age.is_none()
+                    || last_seen_age
+                        .is_some_and(|last_seen_age| last_seen_age > policy.minimum_absence));
+            let area_name = area_name(area);
+            let at_distance = distance
+                .as_ref()
+                .map(|distance| format!(" ({distance})"))
+                .unwrap_or_default();
+            if due && policy.triggers(area) && policy.close_enough(distance.as_ref()) {
+                notifications
+                    .show_after(
+                        name,
+                        &format!("New person entered {area_name}"),
+                        &format!(
+                            "{name} entered the {area_name}{at_distance}\n{last_seen_description}"
+                        ),
+                        if catching_up {
+                            std::time::Duration::ZERO
+                        } else {
+                            policy.arrival_grace
+                        },
+                    )
+                    .await?;
+            } else if due
+                && policy.enabled
+                && config.early_warning.triggers(name, area)
+                && presence.get(name).is_none()
+                && !notifications.contains(name)
+            {
+                notifications
+                    .show_early_warning(
+                        name,
+                        &format!("{name} is approaching"),
+                        &format!(
+                            "{name} entered the {area_name}{at_distance}\n{last_seen_description}"
+                        ),
+                    )
+                    .await?;
+            } else if area == sl_types::radar::Area::ChatRange
+                && notifications.is_early_warning(name)
+            {
+                // they are here now but do not need to be greeted again
+                notifications.close(name).await?;
+            }
+            if let Some(timestamp) = timestamp
+                && area == sl_types::radar::Area::ChatRange
+            {
+                last_seen_in_chat_range.insert(
+                    name.to_lowercase(),
+                    store::LastSeen {
+                        timestamp,
+                        region: presence.region().map(ToOwned::to_owned),
+                    },
+                );
+                db_writer
+                    .last_seen(name, timestamp, presence.region())
+                    .await?;
+            }
+        }
+
+        if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp,
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    ref name,
+                    message: sl_chat_log_parser::avatar_messages::AvatarMessage::LeftArea { area },
+                },
+        }) = parsed_line
+        {
+            if let Some(timestamp) = timestamp
+                && area == sl_types::radar::Area::ChatRange
+            {
+                last_seen_in_chat_range.insert(
+                    name.to_lowercase(),
+                    store::LastSeen {
+                        timestamp,
+                        region: presence.region().map(ToOwned::to_owned),
+                    },
+                );
+                db_writer
+                    .last_seen(name, timestamp, presence.region())
+                    .await?;
+            }
+            // people walking by are never reported
+            if area == sl_types::radar::Area::ChatRange {
+                notifications.cancel_scheduled(name);
+            }
+            // avatars within the leave grace period keep their notification
+            if presence.get(name).is_none()
+                && (area == sl_types::radar::Area::ChatRange
+                    || config.policy(presence.region()).triggers(area)
+                    || (config.early_warning.triggers(name, area)
+                        && notifications.is_early_warning(name)))
+            {
+                notifications.close(name).await?;
+            }
+        }
+
+        // TODO:
+        // leave announcements and left chat range
+        // Examples
+        // "Take care all"
+        // "RL is calling me"
+        // "I have to go"
+        // "I have to head out"
+        // "I have to take off"
+        // "(Good)bye everyone"
+        // "(Good)bye everybody"
+        // "(Good)bye all"
+        // "Dinnertime for me"
+        // "I have to get some sleep"
+        // "It is my bedtime"
+        // "Gotta go"
+        // "Good night all"
+        // "I am going to call it a day"
+        // "I don't feel so good"
+        // "I am going to lie down"
+        // "I am going to get some rest"
+        // "I have to get up early"
+        // (abbreviated versions like tc for take care, gn for good night)
+        // (other people saying good bye or good night to someone or telling them to take care, sweet dreams, sleep well, have a good rest)
+        // (though that might also be the person leaving saying good bye to specific people)
+        //
+        // relog or afk announcements and welcome back
+        // "I have to relog"
+        // "relog, brb"
+        // "afk"
+        // "brb"
+        //
+        // "back"
+
+        // avatars talking in local chat are not just walking by
+        if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp: _,
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    ref name,
+                    message:
+                        sl_chat_log_parser::avatar_messages::AvatarMessage::Chat { message: _, volume }
+                        | sl_chat_log_parser::avatar_messages::AvatarMessage::Emote { message: _, volume },
+                },
+        }) = parsed_line
+            && volume <= sl_types::chat::ChatVolume::Say
+        {
+            notifications.show_scheduled(name).await?;
+        }
+
+        if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp,
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    ref name,
+                    message:
+                        sl_chat_log_parser::avatar_messages::AvatarMessage::Chat {
+                            ref message,
+                            volume,
+                        },
+                },
+        }) = parsed_line
+        {
+            if *name == clean_avatar_name {
+                let lc_message = message.to_lowercase();
+                if let Ok(greeted) = greeting::welcome_greeting_parser()
+                    .parse(&lc_message)
+                    .into_result()
+                {
+                    tracing::debug!("Found welcoming greeting greeting\n{:#?}", greeted);
+                    own_greeting(greeted, timestamp, &presence, &mut notifications).await?;
+                }
+            } else if let Some(timestamp) = timestamp
+                && volume <= sl_types::chat::ChatVolume::Say
+            {
+                last_seen_in_chat_range.insert(
+                    name.to_lowercase(),
+                    store::LastSeen {
+                        timestamp,
+                        region: presence.region().map(ToOwned::to_owned),
+                    },
+                );
+                db_writer
+                    .last_seen(name, timestamp, presence.region())
+                    .await?;
+            }
+        }
+
+        // a teammate greeting someone takes care of them for us
+        if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp,
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    ref name,
+                    ref message,
+                },
+        }) = parsed_line
+            && *name != clean_avatar_name
+            && config.team.is_member(name)
+        {
+            let greeted = match message {
+                sl_chat_log_parser::avatar_messages::AvatarMessage::Chat { message, .. } => {
+                    let lc_message = message.to_lowercase();
+                    greeting::welcome_greeting_parser()
+                        .parse(&lc_message)
+                        .into_result()
+                        .unwrap_or_default()
+                }
+                sl_chat_log_parser::avatar_messages::AvatarMessage::Emote { message, .. } => {
+                    let lc_message = message.to_lowercase();
+                    greeting::emote_greeting_parser(&config.greetings.emote_verbs)
+                        .parse(&lc_message)
+                        .into_result()
+                        .unwrap_or_default()
+                }
+                _ => Vec::new(),
+            };
+            if !greeted.is_empty() {
+                tracing::debug!("Teammate {name} greeted\n{:#?}", greeted);
+                team_greeting(
+                    name,
+                    greeted,
+                    timestamp,
+                    &config,
+                    &mut presence,
+                    &mut notifications,
+                )
+                .await?;
+            }
+        }
+
+        // someone greeting us first should get a greeting back
+        let mut greeted_us = false;
+        if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp: _,
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    ref name,
+                    message:
+                        sl_chat_log_parser::avatar_messages::AvatarMessage::Chat {
+                            ref message,
+                            volume,
+                        },
+                },
+        }) = parsed_line
+            && *name != clean_avatar_name
+            && (!catching_up || options.catch_up_notifications)
+            && config.policy(presence.region()).greets_back(
+                volume,
+                presence
+                    .get(name)
+                    .and_then(|avatar| avatar.distance.as_ref()),
+            )
+            && !presence.get(name).is_some_and(|avatar| avatar.greeted)
+        {
+            let lc_message = message.to_lowercase();
+            if let Ok(greeted) = greeting::welcome_greeting_parser()
+                .parse(&lc_message)
+                .into_result()
+                && greeted
+                    .iter()
+                    .any(|greeted| config.me.is_me(&clean_avatar_name, greeted))
+            {
+                greeted_us = notifications
+                    .show_greeted_us(
+                        name,
+                        &format!("{name} said hello to you"),
+                        &format!("{name}: {message}"),
+                    )
+                    .await?;
+            }
+        }
+
+        // someone talking about us, e.g. while we are tabbed out
+        if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp: _,
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    ref name,
+                    message:
+                        sl_chat_log_parser::avatar_messages::AvatarMessage::Chat {
+                            ref message,
+                            volume,
+                        }
+                        | sl_chat_log_parser::avatar_messages::AvatarMessage::Emote {
+                            ref message,
+                            volume,
+                        },
+                },
+        }) = parsed_line
+            && *name != clean_avatar_name
+            && (!catching_up || options.catch_up_notifications)
+            && config.mentions.triggers(volume)
+        {
+            let lc_message = message.to_lowercase();
+            let own_names = config.me.names(&clean_avatar_name);
+            // greetings addressed to us that got their own notification
+            // do not need a second one
+            if !greeted_us && greeting::mentions(&lc_message, &own_names) {
+                notifications.show_transient(
+                    &format!("{name} mentioned you"),
+                    &format!("{name}: {message}"),
+                );
+            }
+        }
+
+        if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp,
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    ref name,
+                    message:
+                        sl_chat_log_parser::avatar_messages::AvatarMessage::Emote {
+                            ref message, ..
+                        },
+                },
+        }) = parsed_line
+            && *name == clean_avatar_name
+        {
+            let lc_message = message.to_lowercase();
+            if let Ok(greeted) = greeting::emote_greeting_parser(&config.greetings.emote_verbs)
+                .parse(&lc_message)
+                .into_result()
+            {
+                tracing::debug!("Found welcoming emote\n{:#?}", greeted);
+                own_greeting(greeted, timestamp, &presence, &mut notifications).await?;
+            }
+        } else if let Ok(sl_chat_log_parser::ChatLogLine {
+            timestamp: Some(timestamp),
+            event:
+                sl_chat_log_parser::ChatLogEvent::AvatarLine {
+                    name,
+                    message:
+                        sl_chat_log_parser::avatar_messages::AvatarMessage::Emote { message: _, volume },
+                },
+        }) = parsed_line
+            && volume <= sl_types::chat::ChatVolume::Say
+        {
+            last_seen_in_chat_range.insert(
+                name.to_lowercase(),
+                store::LastSeen {
+                    timestamp,
+                    region: presence.region().map(ToOwned::to_owned),
+                },
+            );
+            db_writer
+                .last_seen(&name, timestamp, presence.region())
+                .await?;
+        }
+
+        db_writer
+            .tail_position(LOCAL_CHAT_LOG_FILE_NAME, end)
+            .await?;
+    }
+
+    let closed = notifications.close_all().await?;
+    drop(notifications);
+    drop(db_writer);
+    // the tailer only notices the end of the pipeline when the next line
+    // arrives, messages it did not pass on yet are picked up again on the
+    // next start since their position was not recorded
+    conversations_join_handle.abort();
+    tail_join_handle.abort();
+    match tail_join_handle.await {
+        Ok(result) => result?,
+        Err(e) if e.is_cancelled() => {}
+        Err(e) => return Err(e.into()),
+    }
+    drop(rx);
+    join_handle.await?;
+    db_join_handle.await??;
+    tracing::info!("Shut down after closing {closed} notifications and flushing the database");
+
+    Ok(())
+}
+
+/// did the avatar that just entered chat range return within the grace
+/// periods, continuing its previous visit, the hello notification cancelled
+/// when it left before it was shown is resumed unless we greeted it since
+fn returned_to_chat_range(
+    name: &str,
+    presence: &presence::Presence,
+    notifications: &mut notifications::HelloNotifications,
+) -> bool {
+    let Some(avatar) = presence.get(name).filter(|avatar| avatar.returned) else {
+        return false;
+    };
+    if !avatar.greeted {
+        notifications.resume_scheduled(name);
+    }
+    true
+}
+
+/// we greeted the given names in chat or an emote at log time `at`, closes
+/// their hello notifications or, when we greeted everyone, all of them but
+/// those of late arrivals
+///
+/// # Errors
+///
+/// returns an error if the database writer stopped
+async fn own_greeting(
+    greeted: Vec<String>,
+    at: Option<time::PrimitiveDateTime>,
+    presence: &presence::Presence,
+    notifications: &mut notifications::HelloNotifications,
+) -> Result<(), crate::Error> {
+    for greeted in greeted {
+        if greeting::is_collective_addressee(&greeted) {
+            notifications
+                .greet_everyone(|name| presence.is_late_arrival(name, at))
+                .await?;
+            continue;
+        }
+        let greeted = greeted.to_lowercase();
+        notifications
+            .close_matching(|name| greeting::addresses(name, &greeted))
+            .await?;
+    }
+    Ok(())
+}
+
+/// a teammate greeted the given names, their hello notifications are closed
+/// as if we greeted them or, in annotate mode, marked as greeted by the
+/// teammate
+///
+/// # Errors
+///
+/// returns an error if the database writer stopped
+async fn team_greeting(
+    teammate: &str,
+    greeted: Vec<String>,
+    at: Option<time::PrimitiveDateTime>,
+    config: &config::Config,
+    presence: &mut presence::Presence,
+    notifications: &mut notifications::HelloNotifications,
+) -> Result<(), crate::Error> {
+    if !config.team.annotate {
+        for greeted in &greeted {
+            if greeting::is_collective_addressee(greeted) {
+                presence.greeted_everyone(at);
+            } else {
+                presence.greeted(greeted);
+            }
+        }
+        return own_greeting(greeted, at, presence, notifications).await;
+    }
+    for greeted in greeted {
+        let greeted = greeted.to_lowercase();
+        let matching = if greeting::is_collective_addressee(&greeted) {
+            notifications.matching(|_| true)
+        } else {
+            notifications.matching(|name| greeting::addresses(name, &greeted))
+        };
+        for lc_name in matching {
+            let name = presence
+                .get(&lc_name)
+                .map_or_else(|| lc_name.clone(), |avatar| avatar.name.clone());
+            notifications
+                .annotate(
+                    &lc_name,
+                    &format!("{name} was greeted by {teammate}"),
+                    &format!("{teammate} greeted {name}, you may still want to say hello"),
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// checks a line written to a conversation log for our own greetings, in
+/// an IM a greeting without names greets the other avatar, greeting
+/// everyone only addresses the people in the conversation
+///
+/// # Errors
+///
+/// returns an error if the database writer stopped
+async fn conversation_greeting(
+    conversation_line: &conversations::ConversationLine,
+    own_name: &str,
+    greetings: &config::Greetings,
+    presence: &mut presence::Presence,
+    notifications: &mut notifications::HelloNotifications,
+) -> Result<(), crate::Error> {
+    let Ok(sl_chat_log_parser::ChatLogLine {
+        timestamp: _,
+        event: sl_chat_log_parser::ChatLogEvent::AvatarLine { name, message },
+    }) = sl_chat_log_parser::chat_log_line_parser()
+        .parse(&conversation_line.line)
+        .into_result()
+    else {
+        return Ok(());
+    };
+    if name != own_name {
+        return Ok(());
+    }
+    let greeted = match message {
+        sl_chat_log_parser::avatar_messages::AvatarMessage::Chat { message, .. } => {
+            let lc_message = message.to_lowercase();
+            match greeting::welcome_greeting_parser()
+                .parse(&lc_message)
+                .into_result()
+            {
+                Ok(greeted) => greeted,
+                Err(_) if greeting::is_greeting(&lc_message) => {
+                    conversations::partner(&conversation_line.file_name)
+                        .into_iter()
+                        .collect()
+                }
+                Err(_) => Vec::new(),
+            }
+        }
+        sl_chat_log_parser::avatar_messages::AvatarMessage::Emote { message, .. } => {
+            let lc_message = message.to_lowercase();
+            greeting::emote_greeting_parser(&greetings.emote_verbs)
+                .parse(&lc_message)
+                .into_result()
+                .unwrap_or_default()
+        }
+        _ => Vec::new(),
+    };
+    for greeted in greeted {
+        if greeting::is_collective_addressee(&greeted) {
+            continue;
+        }
+        tracing::debug!(
+            "Greeted {greeted} in conversation {}",
+            conversation_line.file_name
+        );
+        presence.greeted(&greeted);
+        let greeted = greeted.to_lowercase();
+        notifications
+            .close_matching(|name| greeting::addresses(name, &greeted))
+            .await?;
+    }
+    Ok(())
+}
+
+/// take over the notifications a previous run left `pending` for avatars
+/// still in chat range and not greeted, close the others and, if `restore`
+/// is set, show a notification for everyone else in chat range not greeted
+/// yet
+///
+/// # Errors
+///
+/// returns an error if the database writer stopped
+async fn restore_notifications(
+    pending: Vec<(String, notifications::StoredNotification)>,
+    presence: &presence::Presence,
+    notifications: &mut notifications::HelloNotifications,
+    restore: bool,
+) -> Result<(), crate::Error> {
+    for (name, stored) in pending {
+        if presence.get(&name).is_some_and(|avatar| !avatar.greeted)
+            && !notifications.contains(&name)
+        {
+            notifications.adopt(&name, &stored).await?;
+        } else {
+            notifications.discard(&name, &stored).await?;
+        }
+    }
+    if !restore {
+        return Ok(());
+    }
+    for avatar in presence.ungreeted() {
+        if notifications.contains(&avatar.name) {
+            continue;
+        }
+        let since = avatar
+            .entered
+            .map(|entered| format!(" since {entered}"))
+            .unwrap_or_default();
+        let at_distance = avatar
+            .distance
+            .as_ref()
+            .map(|distance| format!(", entered at {distance}"))
+            .unwrap_or_default();
+        notifications
+            .show(
+                &avatar.name,
+                "Person in chat range not greeted yet",
+                &format!("{} is in chat range{since}{at_distance}", avatar.name),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// the viewer session ended or we left the location we were at, everyone
+/// still in chat range (`present`) left at that time so their notifications
+/// are closed and the end is recorded as the time they were last seen in
+/// `region`
+///
+/// # Errors
+///
+/// returns an error if the database writer stopped
+async fn end_presence(
+    reason: &str,
+    ended: Option<time::PrimitiveDateTime>,
+    region: Option<String>,
+    present: Vec<presence::PresentAvatar>,
+    notifications: &mut notifications::HelloNotifications,
+    db_writer: &store::DbWriter,
+    last_seen_in_chat_range: &mut BTreeMap<String, store::LastSeen>,
+) -> Result<(), crate::Error> {
+    let closed = notifications.close_all().await?;
+    if present.is_empty() && closed == 0 {
+        return Ok(());
+    }
+    tracing::info!(
+        "Presence ended ({reason}), {} avatars were still in chat range, closed {closed} notifications",
+        present.len()
+    );
+    let Some(ended) = ended else {
+        return Ok(());
+    };
+    for avatar in present {
+        let lc_name = avatar.name.to_lowercase();
+        if last_seen_in_chat_range
+            .get(&lc_name)
+            .is_some_and(|last_seen| last_seen.timestamp >= ended)
+        {
+            continue;
+        }
+        last_seen_in_chat_range.insert(
+            lc_name,
+            store::LastSeen {
+                timestamp: ended,
+                region: region.clone(),
+            },
+        );
+        db_writer
+            .last_seen(&avatar.name, ended, region.as_deref())
+            .await?;
+    }
+    Ok(())
+}
+
+/// record the region the given avatars were last seen in once we found out
+/// which region that was
+///
+/// # Errors
+///
+/// returns an error if the database writer stopped
+async fn tag_last_seen_region(
+    region: &str,
+    seen: Vec<String>,
+    db_writer: &store::DbWriter,
+    last_seen_in_chat_range: &mut BTreeMap<String, store::LastSeen>,
+) -> Result<(), crate::Error> {
+    for name in seen {
+        if let Some(last_seen) = last_seen_in_chat_range.get_mut(&name) {
+            last_seen.region = Some(region.to_owned());
+        }
+        db_writer.last_seen_region(&name, region).await?;
+    }
+    Ok(())
+}
+
+/// how an area is called in notifications
+const fn area_name(area: sl_types::radar::Area) -> &'static str {
+    match area {
+        sl_types::radar::Area::ChatRange => "chat range",
+        sl_types::radar::Area::DrawDistance => "draw distance",
+        sl_types::radar::Area::Region => "region",
+    }
+}
+
+/// wait for Ctrl-C or, on unix, SIGTERM
+///
+/// # Errors
+///
+/// returns an error if the signal handlers can not be installed
+async fn shutdown_signal() -> Result<(), crate::Error> {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .map_err(crate::Error::SignalError)?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map_err(crate::Error::SignalError)?,
+            _ = sigterm.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c()
+        .await
+        .map_err(crate::Error::SignalError)?;
+    Ok(())
+}
+
+/// The main function mainly just handles setting up tracing
+/// and handling any Err Results.
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let terminal_env_filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::WARN.into())
+        .parse(std::env::var("RUST_LOG").unwrap_or_else(|_| "warn".to_string()))?;
+    let file_env_filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::TRACE.into())
+        .parse(std::env::var("SL_HELLO_GOODBYE_LOG").unwrap_or_else(|_| "trace".to_string()))?;
+    let registry = Registry::default();
+    let registry =
+        registry.with(tracing_subscriber::fmt::Layer::default().with_filter(terminal_env_filter));
+    let log_dir = std::env::var("SL_HELLO_GOODBYE_LOG_DIR");
+    if let Ok(log_dir) = log_dir {
+        let log_file = if let Ok(log_file) = std::env::var("SL_HELLO_GOODBYE_LOG_FILE") {
+            log_file
+        } else {
+            "sl_hello_goodbye.log".to_string()
+        };
+        tracing::info!("Logging to {}/{}", log_dir, log_file);
+        let file_appender = tracing_appender::rolling::never(log_dir, log_file);
+        registry
+            .with(
+                tracing_subscriber::fmt::Layer::default()
+                    .with_writer(file_appender)
+                    .with_filter(file_env_filter),
+            )
+            .init();
+    } else {
+        registry.init();
+    }
+    log_panics::init();
+    match do_stuff().await {
+        Ok(()) => (),
+        Err(e) => {
+            tracing::error!("{e}");
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+    tracing::debug!("Exiting");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// wraps a future and counts how often it is polled, i.e. woken up
+    struct CountPolls<F> {
+        /// the wrapped future
+        inner: std::pin::Pin<Box<F>>,
+        /// the number of polls so far
+        polls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl<F: Future> Future for CountPolls<F> {
+        type Output = F::Output;
+
+        fn poll(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Self::Output> {
+            self.polls
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.inner.as_mut().poll(cx)
+        }
+    }
+
+    /// measures how often the tailer and assembler wake up while no lines
+    /// arrive, run with `--nocapture` to see the numbers
+    #[tokio::test(start_paused = true)]
+    async fn test_pipeline_idle_wakeups() -> Result<(), Error> {
+        let temp_dir = crate::test_util::temp_dir()?;
+        let dir = temp_dir.path();
+        let path = dir.join("chat.txt");
+        fs_err::write(&path, b"").map_err(crate::Error::TailerIoError)?;
+        let (tail_tx, tail_rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+        let (tx, _rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+        let tail_polls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let assemble_polls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let tail_join_handle = tokio::spawn(CountPolls {
+            inner: Box::pin(tailer::tail(path, None, tail_tx)),
+            polls: std::sync::Arc::clone(&tail_polls),
+        });
+        let join_handle = tokio::spawn(CountPolls {
+            inner: Box::pin(assembler::assemble(
+                tail_rx,
+                tx,
+                std::time::Duration::from_millis(500),
+            )),
+            polls: std::sync::Arc::clone(&assemble_polls),
+        });
+        tokio::time::sleep(std::time::Duration::from_secs(60 * 60)).await;
+        let tail_polls = tail_polls.load(std::sync::atomic::Ordering::Relaxed);
+        let assemble_polls = assemble_polls.load(std::sync::atomic::Ordering::Relaxed);
+        println!("wakeups during one idle hour: tailer {tail_polls}, assembler {assemble_polls}");
+        tail_join_handle.abort();
+        join_handle.abort();
+        assert!(
+            tail_polls <= 2,
+            "tailer woke up {tail_polls} times while idle"
+        );
+        assert!(
+            assemble_polls <= 2,
+            "assembler woke up {assemble_polls} times while idle"
+        );
+        Ok(())
+    }
+
+    /// an avatar leaving before its hello is shown and returning within the
+    /// grace periods still gets the hello
+    #[tokio::test(start_paused = true)]
+    async fn test_hello_resumed_after_return() -> Result<(), Error> {
+        let test_db = crate::test_util::TestDb::new()?;
+        let (db_writer, db_join_handle) =
+            test_db.writer(std::time::Duration::ZERO, store::CommitDurability::None);
+        let notifier = notifications::RecordingNotifier::default();
+        let mut notifications = notifications::HelloNotifications::new(
+            db_writer,
+            config::Crowd::default(),
+            Box::new(notifier.clone()),
+        );
+        let mut presence = presence::Presence::default();
+        presence.set_grace_periods(presence::GracePeriods {
+            leave: std::time::Duration::ZERO,
+            reentry: std::time::Duration::from_secs(30),
+        });
+        presence.entered("Foo Bar", None, None);
+        assert!(
+            !returned_to_chat_range("Foo Bar", &presence, &mut notifications),
+            "the first entry is not a return"
+        );
+        notifications
+            .show_after(
+                "Foo Bar",
+                "New person entered chat range",
+                "body",
+                std::time::Duration::from_secs(20),
+            )
+            .await?;
+        tokio::time::advance(std::time::Duration::from_secs(5)).await;
+        presence.left("Foo Bar");
+        notifications.cancel_scheduled("Foo Bar");
+        if presence.get("Foo Bar").is_none() {
+            notifications.close("Foo Bar").await?;
+        }
+        tokio::time::advance(std::time::Duration::from_secs(5)).await;
+        presence.entered("Foo Bar", None, None);
+        assert!(
+            returned_to_chat_range("Foo Bar", &presence, &mut notifications),
+            "entering again within the grace periods is a return"
+        );
+        tokio::time::advance(std::time::Duration::from_secs(20)).await;
+        notifications.show_due().await?;
+        assert_eq!(
+            notifier.summaries(),
+            ["New person entered chat range"],
+            "the hello cancelled by leaving should be shown after returning"
+        );
+        drop(notifications);
+        db_join_handle.await??;
+        Ok(())
+    }
+
+    /// after resuming, notifications are restored for who is in chat range
+    /// once the lines written while we were not running are processed
+    #[tokio::test]
+    async fn test_restore_notifications_after_resume() -> Result<(), Error> {
+        let temp_dir = crate::test_util::temp_dir()?;
+        let path = temp_dir.path().join("chat.txt");
+        fs_err::write(
+            &path,
+            "[2024/05/01 20:00]  Second Life: Alice Resident entered chat range (5.2 m).\n\
+             [2024/05/01 20:00]  Second Life: Carol Resident entered chat range (3.0 m).\n",
+        )
+        .map_err(crate::Error::TailerIoError)?;
+        let (_, processed) =
+            presence::reconstruct(&path, "Me Myself", None, None, config::Greetings::default())?;
+        fs_err::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| {
+                std::io::Write::write_all(
+                    &mut file,
+                    b"[2024/05/01 20:05]  Second Life: Carol Resident left chat range.\n\
+                      [2024/05/01 20:06]  Second Life: Bob Builder entered chat range (12.0 m).\n",
+                )
+            })
+            .map_err(crate::Error::TailerIoError)?;
+        let (mut presence, start_position) = presence::reconstruct(
+            &path,
+            "Me Myself",
+            Some(processed),
+            None,
+            config::Greetings::default(),
+        )?;
+        let mut tailer = tailer::Tailer::open_at(&path, start_position)?;
+        for event in tailer.poll()? {
+            if let tailer::TailEvent::Line { line, .. } = event
+                && let Ok(line) = sl_chat_log_parser::chat_log_line_parser()
+                    .parse(&line)
+                    .into_result()
+            {
+                presence.apply(&line, "Me Myself");
+            }
+        }
+        let test_db = crate::test_util::TestDb::new()?;
+        let (db_writer, db_join_handle) =
+            test_db.writer(std::time::Duration::ZERO, store::CommitDurability::None);
+        let notifier = notifications::RecordingNotifier::default();
+        let mut notifications = notifications::HelloNotifications::new(
+            db_writer,
+            config::Crowd::default(),
+            Box::new(notifier.clone()),
+        );
+        let stored = |id: u32, name: &str| notifications::StoredNotification {
+            id,
+            summary: format!("New person entered chat range: {name}"),
+            body: "body".to_string(),
+        };
+        restore_notifications(
+            vec![
+                ("alice resident".to_string(), stored(100, "Alice")),
+                ("carol resident".to_string(), stored(50, "Carol")),
+            ],
+            &presence,
+            &mut notifications,
+            true,
+        )
+        .await?;
+        assert_eq!(
+            notifier.summaries(),
+            [
+                "New person entered chat range: Alice",
+                "Person in chat range not greeted yet"
+            ],
+            "Alice's notification should be taken over and Bob, who entered while we were not running, should get one"
+        );
+        assert_eq!(
+            notifier.screen(|screen| screen.closed.clone()),
+            [50],
+            "Carol left while we were not running so her notification should be closed"
+        );
+        drop(notifications);
+        db_join_handle.await??;
+        Ok(())
+    }
+}
@@ -0,0 +1,1126 @@
+//! the resident hello notifications we currently show
+//!
+//! Notifications are shown with [`notify_rust::Timeout::Never`] so they stay
+//! on screen until we close them. Their ids are persisted in the database so
+//! a restarted process can take them over again instead of leaving them
+//! behind without a way to close them automatically.
+//!
+//! Hello notifications can also be scheduled to be shown after a delay so
+//! people just walking by never get one, closing a scheduled notification
+//! before it is due cancels it. One cancelled because the avatar left is
+//! scheduled again if they return within the grace periods.
+//!
+//! When many people arrive at once (e.g. at the start of an event) new
+//! arrivals are collected in a single crowd notification listing their names
+//! instead of each getting their own. The same happens for everyone radar
+//! reports right after we arrived somewhere ourselves.
+
+/// a notification listing several avatars instead of one per avatar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Group {
+    /// avatars arriving while many others arrive too
+    Crowd,
+    /// avatars that were already there when we arrived
+    Room,
+}
+
+impl Group {
+    /// the key the notification is stored under instead of an avatar name,
+    /// legacy names never start with a space
+    const fn key(self) -> &'static str {
+        match self {
+            Self::Crowd => " crowd",
+            Self::Room => " room",
+        }
+    }
+
+    /// is the given key the key of a group notification
+    fn is_key(key: &str) -> bool {
+        key.starts_with(' ')
+    }
+
+    /// the summary of the notification listing the given number of avatars
+    fn summary(self, count: usize) -> String {
+        match (self, count) {
+            (Self::Crowd, 1) => "1 person arrived".to_owned(),
+            (Self::Crowd, count) => format!("{count} people arrived"),
+            (Self::Room, 1) => "1 person was here when you arrived".to_owned(),
+            (Self::Room, count) => format!("{count} people were here when you arrived"),
+        }
+    }
+}
+
+/// a hello notification as stored in the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StoredNotification {
+    /// the notification id assigned by the notification server
+    pub(crate) id: u32,
+    /// the summary of the notification
+    pub(crate) summary: String,
+    /// the body of the notification
+    pub(crate) body: String,
+}
+
+/// a hello notification waiting to be shown
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScheduledNotification {
+    /// the legacy name of the avatar the notification is about
+    name: String,
+    /// the summary of the notification
+    summary: String,
+    /// the body of the notification
+    body: String,
+    /// when the notification is shown
+    due: tokio::time::Instant,
+    /// how long after being scheduled the notification is shown
+    delay: std::time::Duration,
+}
+
+/// the contents of a resident hello notification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Notification {
+    /// the summary of the notification
+    pub(crate) summary: String,
+    /// the body of the notification
+    pub(crate) body: String,
+    /// how urgent the notification is
+    pub(crate) urgency: notify_rust::Urgency,
+}
+
+impl Notification {
+    /// a normal urgency notification
+    fn new(summary: &str, body: &str) -> Self {
+        Self {
+            summary: summary.to_owned(),
+            body: body.to_owned(),
+            urgency: notify_rust::Urgency::Normal,
+        }
+    }
+
+    /// a low urgency notification, e.g. for things we do not have to act on
+    fn low(summary: &str, body: &str) -> Self {
+        Self {
+            urgency: notify_rust::Urgency::Low,
+            ..Self::new(summary, body)
+        }
+    }
+}
+
+/// puts notifications on the screen, tests use one that only records them
+pub(crate) trait Notifier: std::fmt::Debug + Send {
+    /// show a resident notification, replacing the one with the given id if
+    /// there is one, returns the id of the shown notification
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the notification server can not be reached
+    fn show(
+        &mut self,
+        notification: &Notification,
+        replaces: Option<u32>,
+    ) -> Result<u32, notify_rust::error::Error>;
+
+    /// close the notification with the given id, also if it was shown by a
+    /// previous run
+    fn close(&mut self, id: u32);
+
+    /// show a notification that goes away on its own and is not tracked
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the notification server can not be reached
+    fn show_transient(
+        &mut self,
+        summary: &str,
+        body: &str,
+    ) -> Result<(), notify_rust::error::Error>;
+}
+
+/// close a notification we have no handle for, e.g. one shown by a previous
+/// run, without showing it again first
+#[cfg(all(unix, not(target_os = "macos")))]
+fn close_by_id(id: u32) {
+    let result = zbus::blocking::Connection::session().and_then(|connection| {
+        connection.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "CloseNotification",
+            &(id,),
+        )
+    });
+    if let Err(e) = result {
+        tracing::error!("Error closing notification {id}: {:?}", e);
+    }
+}
+
+/// other notification backends can not close notifications they did not
+/// show, they are left on screen
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn close_by_id(id: u32) {
+    tracing::debug!("Can not close notification {id} shown by a previous run");
+}
+
+/// shows notifications on the desktop
+#[derive(Debug, Default)]
+pub(crate) struct DesktopNotifier {
+    /// the handles of the notifications we showed by id
+    handles: std::collections::BTreeMap<u32, notify_rust::NotificationHandle>,
+}
+
+impl Notifier for DesktopNotifier {
+    fn show(
+        &mut self,
+        notification: &Notification,
+        replaces: Option<u32>,
+    ) -> Result<u32, notify_rust::error::Error> {
+        let mut desktop_notification = notify_rust::Notification::new();
+        desktop_notification
+            .appname("sl-hello-goodbye")
+            .summary(&notification.summary)
+            .body(&notification.body)
+            .hint(notify_rust::Hint::Resident(true))
+            .urgency(notification.urgency)
+            .timeout(notify_rust::Timeout::Never);
+        if let Some(replaces) = replaces {
+            desktop_notification.id(replaces);
+            self.handles.remove(&replaces);
+        }
+        let notify_handle = desktop_notification.show()?;
+        let id = notify_handle.id();
+        self.handles.insert(id, notify_handle);
+        Ok(id)
+    }
+
+    fn close(&mut self, id: u32) {
+        if let Some(notify_handle) = self.handles.remove(&id) {
+            notify_handle.close();
+        } else {
+            close_by_id(id);
+        }
+    }
+
+    fn show_transient(
+        &mut self,
+        summary: &str,
+        body: &str,
+    ) -> Result<(), notify_rust::error::Error> {
+        notify_rust::Notification::new()
+            .appname("sl-hello-goodbye")
+            .summary(summary)
+            .body(body)
+            .show()?;
+        Ok(())
+    }
+}
+
+/// the hello notifications currently shown, by lower case avatar name
+#[derive(Debug)]
+pub(crate) struct HelloNotifications {
+    /// the ids of the shown notifications
+    handles: std::collections::BTreeMap<String, u32>,
+    /// the lower case names of the avatars whose notification is only an
+    /// early warning that they are approaching
+    early_warnings: std::collections::BTreeSet<String>,
+    /// notifications waiting to be shown by lower case avatar name
+    scheduled: std::collections::BTreeMap<String, ScheduledNotification>,
+    /// scheduled notifications cancelled because the avatar left before
+    /// they were shown, by lower case avatar name, resumed if the avatar
+    /// returns within the grace periods
+    interrupted: std::collections::BTreeMap<String, ScheduledNotification>,
+    /// when recent arrivals were shown, to detect crowds
+    arrivals: std::collections::VecDeque<tokio::time::Instant>,
+    /// the avatars listed in a group notification, the group and legacy
+    /// name by lower case name
+    grouped: std::collections::BTreeMap<String, (Group, String)>,
+    /// until when arrivals are listed in the room notification because we
+    /// just arrived ourselves
+    room_until: Option<tokio::time::Instant>,
+    /// when arrivals are collected in group notifications
+    crowd_config: crate::config::Crowd,
+    /// persists the shown notifications
+    db_writer: crate::store::DbWriter,
+    /// puts the notifications on the screen
+    notifier: Box<dyn Notifier>,
+}
+
+impl HelloNotifications {
+    /// create an empty set of notifications persisted by the given writer
+    /// and shown by the given notifier
+    pub(crate) fn new(
+        db_writer: crate::store::DbWriter,
+        crowd_config: crate::config::Crowd,
+        notifier: Box<dyn Notifier>,
+    ) -> Self {
+        Self {
+            handles: std::collections::BTreeMap::new(),
+            early_warnings: std::collections::BTreeSet::new(),
+            scheduled: std::collections::BTreeMap::new(),
+            interrupted: std::collections::BTreeMap::new(),
+            arrivals: std::collections::VecDeque::new(),
+            grouped: std::collections::BTreeMap::new(),
+            room_until: None,
+            crowd_config,
+            db_writer,
+            notifier,
+        }
+    }
+
+    /// show a notification reminding us to greet someone, replacing an
+    /// existing one for the same avatar
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn show(
+        &mut self,
+        name: &str,
+        summary: &str,
+        body: &str,
+    ) -> Result<(), crate::Error> {
+        let lc_name = name.to_lowercase();
+        self.early_warnings.remove(&lc_name);
+        self.scheduled.remove(&lc_name);
+        self.interrupted.remove(&lc_name);
+        if self.grouped.contains_key(&lc_name) {
+            return Ok(());
+        }
+        let replaces = self.handles.get(&lc_name).copied();
+        if replaces.is_none()
+            && let Some(group) = self.arrival_group()
+        {
+            self.grouped.insert(lc_name, (group, name.to_owned()));
+            return self.update_group(group).await;
+        }
+        self.show_and_store(lc_name, &Notification::new(summary, body), replaces)
+            .await?;
+        Ok(())
+    }
+
+    /// change when arrivals are collected in group notifications, arrivals
+    /// counted so far are forgotten
+    pub(crate) fn set_crowd(&mut self, crowd_config: crate::config::Crowd) {
+        self.crowd_config = crowd_config;
+        self.arrivals.clear();
+        self.room_until = None;
+    }
+
+    /// we arrived somewhere, radar reports everyone already there as
+    /// arriving during the following moments
+    pub(crate) fn arrived(&mut self) {
+        self.room_until = (!self.crowd_config.arrival_window.is_zero())
+            .then(|| tokio::time::Instant::now() + self.crowd_config.arrival_window);
+    }
+
+    /// record a new arrival, returns the group notification to list it in
+    /// if it should not get its own
+    fn arrival_group(&mut self) -> Option<Group> {
+        let now = tokio::time::Instant::now();
+        if self.room_until.is_some_and(|room_until| now < room_until) {
+            return Some(Group::Room);
+        }
+        if self.crowd_config.arrivals == 0 {
+            return None;
+        }
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|arrival| *arrival + self.crowd_config.window <= now)
+        {
+            self.arrivals.pop_front();
+        }
+        self.arrivals.push_back(now);
+        (self.arrivals.len() > self.crowd_config.arrivals).then_some(Group::Crowd)
+    }
+
+    /// show, update or close a group notification to match the avatars in
+    /// the group
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    async fn update_group(&mut self, group: Group) -> Result<(), crate::Error> {
+        let names = self
+            .grouped
+            .values()
+            .filter(|(member_group, _)| *member_group == group)
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            if let Some(id) = self.handles.remove(group.key()) {
+                self.notifier.close(id);
+                self.db_writer
+                    .pending_notification(group.key(), None)
+                    .await?;
+            }
+            return Ok(());
+        }
+        let summary = group.summary(names.len());
+        let body = names.join("\n");
+        let replaces = self.handles.get(group.key()).copied();
+        self.show_and_store(
+            group.key().to_owned(),
+            &Notification::new(&summary, &body),
+            replaces,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// we greeted everyone at once, e.g. with "hi all", closes or cancels
+    /// all hello notifications except those of avatars for which `is_late`
+    /// returns true given their lower case name, i.e. who arrived after we
+    /// started typing the greeting, early warnings stay as the avatars they
+    /// are about are not around yet
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn greet_everyone(
+        &mut self,
+        is_late: impl Fn(&str) -> bool,
+    ) -> Result<(), crate::Error> {
+        let greeted = self
+            .handles
+            .keys()
+            .filter(|name| !Group::is_key(name) && !self.early_warnings.contains(*name))
+            .chain(self.scheduled.keys())
+            .chain(self.grouped.keys())
+            .filter(|name| !is_late(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in greeted {
+            self.close(&name).await?;
+        }
+        Ok(())
+    }
+
+    /// show a notification reminding us to greet someone once `delay` has
+    /// passed unless it is closed before that, without a delay it is shown
+    /// right away
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn show_after(
+        &mut self,
+        name: &str,
+        summary: &str,
+        body: &str,
+        delay: std::time::Duration,
+    ) -> Result<(), crate::Error> {
+        if delay.is_zero() {
+            return self.show(name, summary, body).await;
+        }
+        let lc_name = name.to_lowercase();
+        if self.scheduled.contains_key(&lc_name) {
+            return Ok(());
+        }
+        self.interrupted.remove(&lc_name);
+        self.scheduled.insert(
+            lc_name,
+            ScheduledNotification {
+                name: name.to_owned(),
+                summary: summary.to_owned(),
+                body: body.to_owned(),
+                due: tokio::time::Instant::now() + delay,
+                delay,
+            },
+        );
+        Ok(())
+    }
+
+    /// show the scheduled notification for the given avatar right away, if
+    /// there is one
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn show_scheduled(&mut self, name: &str) -> Result<(), crate::Error> {
+        if let Some(scheduled) = self.scheduled.remove(&name.to_lowercase()) {
+            self.show(&scheduled.name, &scheduled.summary, &scheduled.body)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// cancel the scheduled notification for the given avatar, if any,
+    /// without closing a notification already shown, it is kept to be
+    /// resumed by [`HelloNotifications::resume_scheduled`]
+    pub(crate) fn cancel_scheduled(&mut self, name: &str) {
+        let lc_name = name.to_lowercase();
+        if let Some(scheduled) = self.scheduled.remove(&lc_name) {
+            self.interrupted.insert(lc_name, scheduled);
+        }
+    }
+
+    /// schedule the notification cancelled when the given avatar left again
+    /// with its original delay, e.g. because they returned within the grace
+    /// periods before it was ever shown
+    pub(crate) fn resume_scheduled(&mut self, name: &str) {
+        let lc_name = name.to_lowercase();
+        if let Some(mut scheduled) = self.interrupted.remove(&lc_name) {
+            scheduled.due = tokio::time::Instant::now() + scheduled.delay;
+            self.scheduled.entry(lc_name).or_insert(scheduled);
+        }
+    }
+
+    /// when the next scheduled notification is due, if there are any
+    pub(crate) fn next_due(&self) -> Option<tokio::time::Instant> {
+        self.scheduled.values().map(|scheduled| scheduled.due).min()
+    }
+
+    /// show all scheduled notifications which are due
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn show_due(&mut self) -> Result<(), crate::Error> {
+        let now = tokio::time::Instant::now();
+        let due = self
+            .scheduled
+            .iter()
+            .filter(|(_, scheduled)| scheduled.due <= now)
+            .map(|(lc_name, _)| lc_name.to_owned())
+            .collect::<Vec<_>>();
+        for lc_name in due {
+            self.show_scheduled(&lc_name).await?;
+        }
+        Ok(())
+    }
+
+    /// show a notification reminding us to greet back someone who greeted
+    /// us, replacing the notification shown for the same avatar, it is
+    /// always shown on its own and never counted as an arrival or listed in
+    /// a group notification, the avatar stays listed in one it is already
+    /// listed in until we greet them, returns whether it could be shown
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn show_greeted_us(
+        &mut self,
+        name: &str,
+        summary: &str,
+        body: &str,
+    ) -> Result<bool, crate::Error> {
+        let lc_name = name.to_lowercase();
+        self.early_warnings.remove(&lc_name);
+        self.scheduled.remove(&lc_name);
+        self.interrupted.remove(&lc_name);
+        let replaces = self.handles.get(&lc_name).copied();
+        self.show_and_store(lc_name, &Notification::new(summary, body), replaces)
+            .await
+    }
+
+    /// show a low urgency notification that someone is approaching, it is
+    /// replaced by the hello notification once they are close enough
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn show_early_warning(
+        &mut self,
+        name: &str,
+        summary: &str,
+        body: &str,
+    ) -> Result<(), crate::Error> {
+        let name = name.to_lowercase();
+        let replaces = self.handles.get(&name).copied();
+        self.early_warnings.insert(name.clone());
+        self.show_and_store(name, &Notification::low(summary, body), replaces)
+            .await?;
+        Ok(())
+    }
+
+    /// take over a notification shown by a previous run, replacing it in
+    /// place if it is still on screen
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn adopt(
+        &mut self,
+        name: &str,
+        stored: &StoredNotification,
+    ) -> Result<(), crate::Error> {
+        self.show_and_store(
+            name.to_lowercase(),
+            &Notification::new(&stored.summary, &stored.body),
+            Some(stored.id),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// close a notification shown by a previous run that is no longer needed
+    ///
+    /// if we showed a new notification for the avatar in the meantime its
+    /// stored record is kept
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn discard(
+        &mut self,
+        name: &str,
+        stored: &StoredNotification,
+    ) -> Result<(), crate::Error> {
+        self.notifier.close(stored.id);
+        let name = name.to_lowercase();
+        if self.handles.contains_key(&name) {
+            return Ok(());
+        }
+        self.db_writer.pending_notification(&name, None).await
+    }
+
+    /// show a notification and persist it, returns whether it could be
+    /// shown
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    async fn show_and_store(
+        &mut self,
+        name: String,
+        notification: &Notification,
+        replaces: Option<u32>,
+    ) -> Result<bool, crate::Error> {
+        match self.notifier.show(notification, replaces) {
+            Ok(id) => {
+                self.db_writer
+                    .pending_notification(
+                        &name,
+                        Some(StoredNotification {
+                            id,
+                            summary: notification.summary.clone(),
+                            body: notification.body.clone(),
+                        }),
+                    )
+                    .await?;
+                self.handles.insert(name, id);
+                Ok(true)
+            }
+            Err(e) => {
+                tracing::error!("Error sending notification: {:?}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    /// show a notification that goes away on its own and is not tracked,
+    /// e.g. that someone mentioned us
+    pub(crate) fn show_transient(&mut self, summary: &str, body: &str) {
+        if let Err(e) = self.notifier.show_transient(summary, body) {
+            tracing::error!("Error sending notification: {:?}", e);
+        }
+    }
+
+    /// is a notification shown or scheduled for the given avatar
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.handles.contains_key(&name)
+            || self.scheduled.contains_key(&name)
+            || self.grouped.contains_key(&name)
+    }
+
+    /// is the notification shown for the given avatar only an early warning
+    pub(crate) fn is_early_warning(&self, name: &str) -> bool {
+        self.early_warnings.contains(&name.to_lowercase())
+    }
+
+    /// close or cancel the notification for the given avatar, if any
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn close(&mut self, name: &str) -> Result<(), crate::Error> {
+        let name = name.to_lowercase();
+        self.early_warnings.remove(&name);
+        self.scheduled.remove(&name);
+        if let Some((group, _)) = self.grouped.remove(&name) {
+            self.update_group(group).await?;
+        }
+        if let Some(id) = self.handles.remove(&name) {
+            self.notifier.close(id);
+            self.db_writer.pending_notification(&name, None).await?;
+        }
+        Ok(())
+    }
+
+    /// replace the notification for the given avatar with a low urgency one,
+    /// e.g. because someone else greeted them, scheduled notifications are
+    /// shown right away while avatars listed in a group notification are
+    /// removed from it and early warnings are left alone
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn annotate(
+        &mut self,
+        name: &str,
+        summary: &str,
+        body: &str,
+    ) -> Result<(), crate::Error> {
+        let name = name.to_lowercase();
+        if self.early_warnings.contains(&name) {
+            return Ok(());
+        }
+        if let Some((group, _)) = self.grouped.remove(&name) {
+            return self.update_group(group).await;
+        }
+        if self.scheduled.remove(&name).is_none() && !self.handles.contains_key(&name) {
+            return Ok(());
+        }
+        let replaces = self.handles.get(&name).copied();
+        self.show_and_store(name, &Notification::low(summary, body), replaces)
+            .await?;
+        Ok(())
+    }
+
+    /// close all notifications and cancel all scheduled ones, returns how
+    /// many were closed
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn close_all(&mut self) -> Result<usize, crate::Error> {
+        let count = self.handles.len();
+        self.interrupted.clear();
+        self.close_matching(|_| true).await?;
+        Ok(count)
+    }
+
+    /// close or cancel the notifications for all avatars whose lower case
+    /// name matches the given predicate
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the database writer stopped
+    pub(crate) async fn close_matching(
+        &mut self,
+        predicate: impl Fn(&str) -> bool,
+    ) -> Result<(), crate::Error> {
+        for name in self.matching(predicate) {
+            self.close(&name).await?;
+        }
+        Ok(())
+    }
+
+    /// the lower case names of all avatars with a notification shown or
+    /// scheduled whose lower case name matches the given predicate
+    pub(crate) fn matching(&self, predicate: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut names = self
+            .handles
+            .keys()
+            .filter(|name| !Group::is_key(name))
+            .chain(self.scheduled.keys())
+            .chain(self.grouped.keys())
+            .filter(|name| predicate(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// what a [`RecordingNotifier`] would have put on the screen
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct Screen {
+    /// the resident notifications on screen by id
+    pub(crate) shown: std::collections::BTreeMap<u32, Notification>,
+    /// the summaries of the transient notifications shown
+    pub(crate) transient: Vec<String>,
+    /// the ids of the notifications closed
+    pub(crate) closed: Vec<u32>,
+    /// the id of the last notification shown
+    last_id: u32,
+}
+
+/// a notifier that only records what it would show, shares the record with
+/// its clones so tests can keep one to look at
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RecordingNotifier {
+    /// what would be on screen
+    screen: std::sync::Arc<std::sync::Mutex<Screen>>,
+}
+
+#[cfg(test)]
+impl RecordingNotifier {
+    /// look at what would be on screen
+    pub(crate) fn screen<R>(&self, f: impl FnOnce(&Screen) -> R) -> R {
+        f(&self
+            .screen
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner))
+    }
+
+    /// the summaries of the resident notifications on screen, in the order
+    /// they were first shown
+    pub(crate) fn summaries(&self) -> Vec<String> {
+        self.screen(|screen| {
+            screen
+                .shown
+                .values()
+                .map(|notification| notification.summary.clone())
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+impl Notifier for RecordingNotifier {
+    fn show(
+        &mut self,
+        notification: &Notification,
+        replaces: Option<u32>,
+    ) -> Result<u32, notify_rust::error::Error> {
+        let mut screen = self
+            .screen
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let id = replaces.unwrap_or(screen.last_id + 1);
+        screen.last_id = screen.last_id.max(id);
+        screen.shown.insert(id, notification.clone());
+        drop(screen);
+        Ok(id)
+    }
+
+    fn close(&mut self, id: u32) {
+        let mut screen = self
+            .screen
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        screen.shown.remove(&id);
+        screen.closed.push(id);
+    }
+
+    fn show_transient(
+        &mut self,
+        summary: &str,
+        _body: &str,
+    ) -> Result<(), notify_rust::error::Error> {
+        self.screen
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .transient
+            .push(summary.to_owned());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scheduled_notifications_cancel() -> Result<(), crate::Error> {
+        let test_db = crate::test_util::TestDb::new()?;
+        let (db_writer, join_handle) = test_db.writer(
+            std::time::Duration::ZERO,
+            crate::store::CommitDurability::None,
+        );
+        let notifier = RecordingNotifier::default();
+        let mut notifications = HelloNotifications::new(
+            db_writer,
+            crate::config::Crowd::default(),
+            Box::new(notifier.clone()),
+        );
+        let delay = std::time::Duration::from_secs(20);
+        notifications
+            .show_after("Foo Bar", "summary", "body", delay)
+            .await?;
+        notifications
+            .show_after("Baz Qux", "summary", "body", delay)
+            .await?;
+        assert!(
+            notifications.contains("foo bar"),
+            "scheduled notifications count as shown"
+        );
+        assert_eq!(
+            notifications.next_due(),
+            Some(tokio::time::Instant::now() + delay)
+        );
+        assert!(
+            notifier.summaries().is_empty(),
+            "scheduled notifications are not on screen yet"
+        );
+        notifications.cancel_scheduled("Foo Bar");
+        notifications
+            .close_matching(|name| name.contains("baz"))
+            .await?;
+        assert_eq!(notifications.next_due(), None);
+        assert_eq!(notifications.close_all().await?, 0);
+        assert!(
+            notifier.summaries().is_empty(),
+            "cancelled notifications are never shown"
+        );
+        drop(notifications);
+        join_handle.await??;
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_crowd() -> Result<(), crate::Error> {
+        let test_db = crate::test_util::TestDb::new()?;
+        let (db_writer, join_handle) = test_db.writer(
+            std::time::Duration::ZERO,
+            crate::store::CommitDurability::None,
+        );
+        let notifier = RecordingNotifier::default();
+        let mut notifications = HelloNotifications::new(
+            db_writer,
+            crate::config::Crowd {
+                arrivals: 2,
+                window: std::time::Duration::from_secs(60),
+                arrival_window: std::time::Duration::from_secs(10),
+            },
+            Box::new(notifier.clone()),
+        );
+        for name in [
+            "Alice Resident",
+            "Bob Builder",
+            "Carol Resident",
+            "Dave Resident",
+        ] {
+            notifications
+                .show(name, &format!("{name} arrived"), "body")
+                .await?;
+        }
+        assert_eq!(
+            notifier.summaries(),
+            [
+                "Alice Resident arrived",
+                "Bob Builder arrived",
+                "2 people arrived"
+            ],
+            "arrivals beyond the limit should be collected in the crowd"
+        );
+        assert_eq!(
+            notifier.screen(|screen| {
+                screen
+                    .shown
+                    .values()
+                    .map(|notification| notification.body.clone())
+                    .collect::<Vec<_>>()
+            }),
+            ["body", "body", "Carol Resident\nDave Resident"],
+            "the crowd notification lists the names"
+        );
+        notifications
+            .close_matching(|name| name.contains("carol"))
+            .await?;
+        assert_eq!(
+            notifier.summaries(),
+            [
+                "Alice Resident arrived",
+                "Bob Builder arrived",
+                "1 person arrived"
+            ],
+            "closing a member updates the crowd notification"
+        );
+        notifications.close("Dave Resident").await?;
+        assert_eq!(
+            notifier.summaries(),
+            ["Alice Resident arrived", "Bob Builder arrived"],
+            "the empty crowd notification should be closed"
+        );
+        tokio::time::advance(std::time::Duration::from_secs(60)).await;
+        notifications
+            .show("Erin Resident", "Erin Resident arrived", "body")
+            .await?;
+        assert!(
+            notifier
+                .summaries()
+                .contains(&"Erin Resident arrived".to_string()),
+            "arrivals after the window should get their own notification"
+        );
+        notifications.arrived();
+        notifications
+            .show("Fred Resident", "Fred Resident arrived", "body")
+            .await?;
+        tokio::time::advance(std::time::Duration::from_secs(10)).await;
+        notifications
+            .show("Gina Resident", "Gina Resident arrived", "body")
+            .await?;
+        assert_eq!(
+            notifier.summaries(),
+            [
+                "Alice Resident arrived",
+                "Bob Builder arrived",
+                "Erin Resident arrived",
+                "1 person was here when you arrived",
+                "Gina Resident arrived"
+            ],
+            "only arrivals right after ours were already in the room"
+        );
+        notifications
+            .greet_everyone(|name| name == "gina resident")
+            .await?;
+        assert_eq!(
+            notifier.summaries(),
+            ["Gina Resident arrived"],
+            "greeting everyone should close all notifications but the late arrival's"
+        );
+        notifications
+            .show_after(
+                "Hank Resident",
+                "Hank Resident arrived",
+                "body",
+                std::time::Duration::from_secs(20),
+            )
+            .await?;
+        notifications
+            .greet_everyone(|name| name == "hank resident")
+            .await?;
+        assert!(
+            notifications.contains("Hank Resident"),
+            "arrivals just before greeting everyone still need a greeting"
+        );
+        assert!(
+            !notifications.contains("Gina Resident"),
+            "greeting everyone should include earlier arrivals"
+        );
+        notifications
+            .annotate("Hank Resident", "Hank was greeted by Bob", "body")
+            .await?;
+        assert_eq!(
+            notifications.next_due(),
+            None,
+            "annotating a scheduled notification shows it right away"
+        );
+        assert_eq!(
+            notifier.screen(|screen| {
+                screen
+                    .shown
+                    .values()
+                    .map(|notification| (notification.summary.clone(), notification.urgency))
+                    .collect::<Vec<_>>()
+            }),
+            [(
+                "Hank was greeted by Bob".to_string(),
+                notify_rust::Urgency::Low
+            )],
+            "the annotated notification has low urgency"
+        );
+        notifications.close_all().await?;
+        assert!(
+            notifier.summaries().is_empty(),
+            "closing everything leaves nothing on screen"
+        );
+        notifications.arrived();
+        notifications.set_crowd(crate::config::Crowd {
+            arrivals: 0,
+            window: std::time::Duration::from_secs(60),
+            arrival_window: std::time::Duration::ZERO,
+        });
+        for name in ["Ivy Resident", "Jack Resident", "Kim Resident"] {
+            notifications
+                .show(name, &format!("{name} arrived"), "body")
+                .await?;
+        }
+        assert_eq!(
+            notifier.summaries(),
+            [
+                "Ivy Resident arrived",
+                "Jack Resident arrived",
+                "Kim Resident arrived"
+            ],
+            "without crowds every arrival gets its own notification"
+        );
+        drop(notifications);
+        join_handle.await??;
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_greeted_us() -> Result<(), crate::Error> {
+        let test_db = crate::test_util::TestDb::new()?;
+        let (db_writer, join_handle) = test_db.writer(
+            std::time::Duration::ZERO,
+            crate::store::CommitDurability::None,
+        );
+        let notifier = RecordingNotifier::default();
+        let mut notifications = HelloNotifications::new(
+            db_writer,
+            crate::config::Crowd {
+                arrivals: 1,
+                ..crate::config::Crowd::default()
+            },
+            Box::new(notifier.clone()),
+        );
+        for name in ["Alice Resident", "Bob Builder"] {
+            notifications
+                .show(name, &format!("{name} arrived"), "body")
+                .await?;
+        }
+        assert!(
+            notifications
+                .show_greeted_us("Bob Builder", "Bob Builder said hello to you", "body")
+                .await?,
+            "the reminder should be shown"
+        );
+        notifications
+            .show("Carol Resident", "Carol Resident arrived", "body")
+            .await?;
+        assert_eq!(
+            notifier.summaries(),
+            [
+                "Alice Resident arrived",
+                "2 people arrived",
+                "Bob Builder said hello to you"
+            ],
+            "the greeting of a grouped avatar should be shown on its own without counting as an arrival"
+        );
+        notifications.close("Bob Builder").await?;
+        assert_eq!(
+            notifier.summaries(),
+            ["Alice Resident arrived", "1 person arrived"],
+            "greeting back should close the reminder and remove them from the group"
+        );
+        drop(notifications);
+        join_handle.await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_discard() -> Result<(), crate::Error> {
+        let test_db = crate::test_util::TestDb::new()?;
+        let (db_writer, join_handle) = test_db.writer(
+            std::time::Duration::ZERO,
+            crate::store::CommitDurability::None,
+        );
+        let stored = StoredNotification {
+            id: 7,
+            summary: "summary".to_string(),
+            body: "body".to_string(),
+        };
+        db_writer
+            .pending_notification("foo bar", Some(stored.clone()))
+            .await?;
+        let notifier = RecordingNotifier::default();
+        let mut notifications = HelloNotifications::new(
+            db_writer,
+            crate::config::Crowd::default(),
+            Box::new(notifier.clone()),
+        );
+        notifications.discard("Foo Bar", &stored).await?;
+        assert_eq!(
+            notifier.screen(|screen| (screen.shown.len(), screen.closed.clone())),
+            (0, vec![7]),
+            "the old notification should be closed without showing it again"
+        );
+        drop(notifications);
+        join_handle.await??;
+        assert_eq!(
+            crate::store::read_pending_notifications(&test_db.db)?,
+            [],
+            "the stored notification should be dropped"
+        );
+        Ok(())
+    }
+}
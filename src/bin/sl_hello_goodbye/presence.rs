@@ -0,0 +1,631 @@
+//! tracks which avatars are currently in chat range and whether we
+//! greeted them yet
+//!
+//! Avatars standing around the edge of chat range produce a stream of
+//! enter and leave lines. An avatar that left chat range is only considered
+//! gone once it stayed away for the leave grace period and if it returns
+//! within the re-entry grace period after that it continues its previous
+//! visit instead of starting a new one.
+
+use chumsky::Parser as _;
+
+/// an avatar currently in chat range
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PresentAvatar {
+    /// the legacy name of the avatar as it appears in the log
+    pub(crate) name: String,
+    /// when the avatar entered chat range, if known
+    pub(crate) entered: Option<time::PrimitiveDateTime>,
+    /// how far away the avatar was when it entered chat range, if known,
+    /// small distances usually mean they arrived right next to us by
+    /// teleport instead of walking or flying in
+    pub(crate) distance: Option<sl_types::map::Distance>,
+    /// whether we greeted the avatar since it entered chat range
+    pub(crate) greeted: bool,
+    /// whether the avatar most recently entered chat range by returning
+    /// within the grace periods, continuing its previous visit
+    pub(crate) returned: bool,
+    /// when the avatar left chat range if it did and is still within the
+    /// leave grace period
+    leaving: Option<tokio::time::Instant>,
+}
+
+/// how long avatars leaving chat range are kept around
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct GracePeriods {
+    /// how long an avatar that left chat range is still considered present
+    pub(crate) leave: std::time::Duration,
+    /// how long after the leave grace period an avatar entering again
+    /// continues its previous visit
+    pub(crate) reentry: std::time::Duration,
+}
+
+/// the avatars currently in chat range
+#[derive(Debug, Default)]
+pub(crate) struct Presence {
+    /// present avatars by lower case legacy name
+    avatars: std::collections::BTreeMap<String, PresentAvatar>,
+    /// avatars that are gone but may still continue their visit by
+    /// returning before the given time, by lower case legacy name
+    recently_left: std::collections::BTreeMap<String, (PresentAvatar, tokio::time::Instant)>,
+    /// how long leaving avatars are kept around
+    grace_periods: GracePeriods,
+    /// how our own greetings are interpreted
+    greetings: crate::config::Greetings,
+    /// where we are
+    location: crate::location::Location,
+}
+
+impl Presence {
+    /// change how long leaving avatars are kept around
+    pub(crate) const fn set_grace_periods(&mut self, grace_periods: GracePeriods) {
+        self.grace_periods = grace_periods;
+    }
+
+    /// change how our own greetings are interpreted
+    pub(crate) fn set_greetings(&mut self, greetings: crate::config::Greetings) {
+        self.greetings = greetings;
+    }
+
+    /// an avatar entered chat range, re-entering within the grace periods
+    /// continues the previous visit and does not reset whether we greeted
+    /// them
+    pub(crate) fn entered(
+        &mut self,
+        name: &str,
+        timestamp: Option<time::PrimitiveDateTime>,
+        distance: Option<sl_types::map::Distance>,
+    ) {
+        let lc_name = name.to_lowercase();
+        if let Some(avatar) = self.avatars.get_mut(&lc_name) {
+            avatar.returned = avatar.leaving.take().is_some();
+            return;
+        }
+        let now = tokio::time::Instant::now();
+        if let Some((mut avatar, deadline)) = self.recently_left.remove(&lc_name)
+            && now < deadline
+        {
+            avatar.returned = true;
+            self.avatars.insert(lc_name, avatar);
+            return;
+        }
+        self.avatars.insert(
+            lc_name,
+            PresentAvatar {
+                name: name.to_owned(),
+                entered: timestamp,
+                distance,
+                greeted: false,
+                returned: false,
+                leaving: None,
+            },
+        );
+    }
+
+    /// an avatar left chat range, returns the avatar if it is gone right
+    /// away because there is no leave grace period
+    pub(crate) fn left(&mut self, name: &str) -> Option<PresentAvatar> {
+        let lc_name = name.to_lowercase();
+        if self.grace_periods.leave.is_zero() {
+            let avatar = self.avatars.remove(&lc_name)?;
+            self.remember(lc_name, avatar.clone(), tokio::time::Instant::now());
+            return Some(avatar);
+        }
+        if let Some(avatar) = self.avatars.get_mut(&lc_name)
+            && avatar.leaving.is_none()
+        {
+            avatar.leaving = Some(tokio::time::Instant::now());
+        }
+        None
+    }
+
+    /// remember an avatar that is gone for the re-entry grace period
+    fn remember(&mut self, lc_name: String, avatar: PresentAvatar, gone: tokio::time::Instant) {
+        if !self.grace_periods.reentry.is_zero() {
+            self.recently_left
+                .insert(lc_name, (avatar, gone + self.grace_periods.reentry));
+        }
+    }
+
+    /// when the next leaving avatar is gone, if there are any
+    pub(crate) fn next_expiry(&self) -> Option<tokio::time::Instant> {
+        self.avatars
+            .values()
+            .filter_map(|avatar| avatar.leaving)
+            .min()
+            .map(|leaving| leaving + self.grace_periods.leave)
+    }
+
+    /// remove the avatars whose leave grace period is over, returns them
+    pub(crate) fn expire(&mut self) -> Vec<PresentAvatar> {
+        let now = tokio::time::Instant::now();
+        self.recently_left
+            .retain(|_, (_, deadline)| now < *deadline);
+        let expired = self
+            .avatars
+            .iter()
+            .filter_map(|(lc_name, avatar)| {
+                let gone = avatar.leaving? + self.grace_periods.leave;
+                (gone <= now).then(|| (lc_name.to_owned(), gone))
+            })
+            .collect::<Vec<_>>();
+        let mut gone_avatars = Vec::new();
+        for (lc_name, gone) in expired {
+            if let Some(avatar) = self.avatars.remove(&lc_name) {
+                self.remember(lc_name, avatar.clone(), gone);
+                gone_avatars.push(avatar);
+            }
+        }
+        gone_avatars
+    }
+
+    /// we greeted someone by their name or whole words of it (see
+    /// [`crate::greeting::addresses`]), returns the lower case names of the
+    /// present avatars this matched
+    pub(crate) fn greeted(&mut self, greeted: &str) -> Vec<String> {
+        let mut matched = Vec::new();
+        for (name, avatar) in &mut self.avatars {
+            if crate::greeting::addresses(name, greeted) {
+                avatar.greeted = true;
+                matched.push(name.to_owned());
+            }
+        }
+        matched
+    }
+
+    /// we greeted everyone at once at the given log time, e.g. with
+    /// "hi all", avatars that entered chat range too recently most likely
+    /// arrived while we were typing and are not greeted by it
+    pub(crate) fn greeted_everyone(&mut self, at: Option<time::PrimitiveDateTime>) {
+        let late_arrivals = self.greetings.late_arrivals;
+        for avatar in self.avatars.values_mut() {
+            if !is_late(avatar, at, late_arrivals) {
+                avatar.greeted = true;
+            }
+        }
+    }
+
+    /// did the avatar with the given name enter chat range less than the
+    /// configured late arrivals before greeting everyone at the given log
+    /// time, avatars that are gone or without timestamps are not late
+    pub(crate) fn is_late_arrival(&self, name: &str, at: Option<time::PrimitiveDateTime>) -> bool {
+        self.get(name)
+            .is_some_and(|avatar| is_late(avatar, at, self.greetings.late_arrivals))
+    }
+
+    /// the avatar with the given name if it is in chat range
+    pub(crate) fn get(&self, name: &str) -> Option<&PresentAvatar> {
+        self.avatars.get(&name.to_lowercase())
+    }
+
+    /// the viewer session ended, everyone is gone, returns who was still
+    /// in chat range
+    pub(crate) fn end_session(&mut self) -> Vec<PresentAvatar> {
+        self.location.end_session();
+        self.leave()
+    }
+
+    /// we left the location we were at, everyone is gone, returns who was
+    /// still in chat range
+    pub(crate) fn leave(&mut self) -> Vec<PresentAvatar> {
+        self.recently_left.clear();
+        std::mem::take(&mut self.avatars).into_values().collect()
+    }
+
+    /// the region we are in, if known
+    pub(crate) fn region(&self) -> Option<&str> {
+        self.location.region()
+    }
+
+    /// the avatars in chat range we did not greet yet
+    pub(crate) fn ungreeted(&self) -> impl Iterator<Item = &PresentAvatar> {
+        self.avatars.values().filter(|avatar| !avatar.greeted)
+    }
+
+    /// update presence from a chat log line, `own_name` is the legacy name
+    /// of our own avatar whose chat lines are checked for greetings
+    ///
+    /// returns what changed about our own location, when we left it the
+    /// caller decides what to do with everyone who was around us by calling
+    /// [`Presence::leave`]
+    pub(crate) fn apply(
+        &mut self,
+        line: &sl_chat_log_parser::ChatLogLine,
+        own_name: &str,
+    ) -> Option<crate::location::LocationChange> {
+        let change = self.location.apply(line);
+        let sl_chat_log_parser::ChatLogEvent::AvatarLine { name, message } = &line.event else {
+            return change;
+        };
+        match message {
+            sl_chat_log_parser::avatar_messages::AvatarMessage::EnteredArea {
+                area: sl_types::radar::Area::ChatRange,
+                distance,
+            } => {
+                self.entered(name, line.timestamp, distance.clone());
+            }
+            sl_chat_log_parser::avatar_messages::AvatarMessage::LeftArea {
+                area: sl_types::radar::Area::ChatRange,
+            } => {
+                self.left(name);
+            }
+            sl_chat_log_parser::avatar_messages::AvatarMessage::Chat { message, volume: _ }
+                if name == own_name =>
+            {
+                let lc_message = message.to_lowercase();
+                if let Ok(greeted) = crate::greeting::welcome_greeting_parser()
+                    .parse(&lc_message)
+                    .into_result()
+                {
+                    self.own_greeting(greeted, line.timestamp);
+                }
+            }
+            sl_chat_log_parser::avatar_messages::AvatarMessage::Emote { message, volume: _ }
+                if name == own_name =>
+            {
+                let lc_message = message.to_lowercase();
+                if let Ok(greeted) =
+                    crate::greeting::emote_greeting_parser(&self.greetings.emote_verbs)
+                        .parse(&lc_message)
+                        .into_result()
+                {
+                    self.own_greeting(greeted, line.timestamp);
+                }
+            }
+            _ => {}
+        }
+        change
+    }
+
+    /// we greeted the given names at the given log time
+    fn own_greeting(&mut self, greeted: Vec<String>, at: Option<time::PrimitiveDateTime>) {
+        for greeted in greeted {
+            if crate::greeting::is_collective_addressee(&greeted) {
+                self.greeted_everyone(at);
+            } else {
+                self.greeted(&greeted);
+            }
+        }
+    }
+}
+
+/// did the avatar enter chat range less than `late_arrivals` before the log
+/// time `at`, log timestamps often only have minute precision so anyone
+/// entering in the same minute as a greeting with a non-zero
+/// `late_arrivals` counts as late
+fn is_late(
+    avatar: &PresentAvatar,
+    at: Option<time::PrimitiveDateTime>,
+    late_arrivals: std::time::Duration,
+) -> bool {
+    let (Some(entered), Some(at)) = (avatar.entered, at) else {
+        return false;
+    };
+    !late_arrivals.is_zero() && at < entered + late_arrivals
+}
+
+/// does this line start a new viewer session
+///
+/// that is the case for the messages the viewer writes while logging in
+/// and, if an `idle_gap` is given, for the first line after no line was
+/// written for that long, since a crashed or closed viewer does not write
+/// anything else we could detect the end of a session by, the gap is opt-in
+/// because being away from the keyboard with the viewer still logged in
+/// leaves the same gap in the log
+pub(crate) fn starts_session(
+    line: &sl_chat_log_parser::ChatLogLine,
+    previous_timestamp: Option<time::PrimitiveDateTime>,
+    idle_gap: Option<std::time::Duration>,
+) -> bool {
+    if let sl_chat_log_parser::ChatLogEvent::SystemMessage {
+        message:
+            sl_chat_log_parser::system_messages::SystemMessage::EarlyFirestormStartupMessage { .. },
+    } = line.event
+    {
+        return true;
+    }
+    if let (Some(timestamp), Some(previous_timestamp), Some(idle_gap)) =
+        (line.timestamp, previous_timestamp, idle_gap)
+    {
+        return timestamp - previous_timestamp > idle_gap;
+    }
+    false
+}
+
+/// rebuild presence by replaying the local chat log from its start up to
+/// the given position or, if there is none, its current end
+///
+/// Firestorm starts a new chat.txt on every login so the start of the file
+/// is usually the start of the current session, other session starts in
+/// the file are detected with [`starts_session`]. If the viewer rotated the
+/// file since the given position was read the old file is replayed instead,
+/// if the position is in some other file nothing is replayed since the whole
+/// file is still to be processed.
+///
+/// our own greetings in the replayed lines are interpreted according to
+/// `greetings` which the returned presence keeps using
+///
+/// returns the reconstructed presence and the position to continue tailing
+/// at
+///
+/// # Errors
+///
+/// returns an error if the log file can not be read
+pub(crate) fn reconstruct(
+    path: &std::path::Path,
+    own_name: &str,
+    up_to: Option<crate::tailer::TailPosition>,
+    idle_gap: Option<std::time::Duration>,
+    greetings: crate::config::Greetings,
+) -> Result<(Presence, crate::tailer::TailPosition), crate::Error> {
+    let replayed_path = up_to.map_or_else(
+        || path.to_path_buf(),
+        |position| crate::tailer::file_holding(path, position),
+    );
+    let mut tailer = crate::tailer::Tailer::open_at_start(&replayed_path)?;
+    let events = tailer.poll()?;
+    let up_to = up_to.unwrap_or_else(|| tailer.position());
+    let mut assembler = crate::assembler::MessageAssembler::default();
+    let mut messages = Vec::new();
+    for event in events {
+        if let crate::tailer::TailEvent::Line { line, end } = event {
+            if end.identity != up_to.identity || end.offset > up_to.offset {
+                break;
+            }
+            messages.extend(assembler.push_line(line, end));
+        }
+    }
+    messages.extend(assembler.flush());
+    let mut presence = Presence::default();
+    presence.set_greetings(greetings);
+    let mut previous_timestamp = None;
+    for message in &messages {
+        if let Ok(line) = sl_chat_log_parser::chat_log_line_parser()
+            .parse(&message.text)
+            .into_result()
+        {
+            if starts_session(&line, previous_timestamp, idle_gap) {
+                presence.end_session();
+            }
+            if let Some(crate::location::LocationChange::Left { .. }) =
+                presence.apply(&line, own_name)
+            {
+                presence.leave();
+            }
+            previous_timestamp = line.timestamp.or(previous_timestamp);
+        }
+    }
+    tracing::debug!(
+        "Reconstructed presence from {} messages, {} avatars in chat range",
+        messages.len(),
+        presence.avatars.len()
+    );
+    Ok((presence, up_to))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_reconstruct_presence() -> Result<(), crate::Error> {
+        let temp_dir = crate::test_util::temp_dir()?;
+        let dir = temp_dir.path();
+        let path = dir.join("chat.txt");
+        fs_err::write(
+            &path,
+            "[2024/05/01 20:00]  Second Life: Alice Resident entered chat range (5.2 m).\n\
+             [2024/05/01 20:01]  Second Life: Bob Builder entered chat range (12.0 m).\n\
+             [2024/05/01 20:02]  Second Life: Carol Resident entered chat range (3.0 m).\n\
+             [2024/05/01 20:02]  Second Life: Dave Resident entered chat range (4.0 m).\n\
+             [2024/05/01 20:03]  Me Myself: hi bob\n\
+             [2024/05/01 20:03]  Me Myself: /me salutes dave\n\
+             [2024/05/01 20:04]  Second Life: Carol Resident left chat range.\n",
+        )
+        .map_err(crate::Error::TailerIoError)?;
+        let (presence, position) = reconstruct(
+            &path,
+            "Me Myself",
+            None,
+            None,
+            crate::config::Greetings {
+                emote_verbs: vec!["salutes".to_string()],
+                ..crate::config::Greetings::default()
+            },
+        )?;
+        assert_eq!(
+            presence
+                .ungreeted()
+                .map(|avatar| avatar.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Alice Resident"],
+            "the configured emote verbs should greet Dave"
+        );
+        assert_eq!(
+            presence.avatars.len(),
+            3,
+            "Bob and Dave should still be present after being greeted"
+        );
+        assert_eq!(
+            position.offset,
+            fs_err::metadata(&path)
+                .map_err(crate::Error::TailerIoError)?
+                .len()
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reconstruct_presence_after_rotation() -> Result<(), crate::Error> {
+        let temp_dir = crate::test_util::temp_dir()?;
+        let path = temp_dir.path().join("chat.txt");
+        fs_err::write(
+            &path,
+            "[2024/05/01 20:00]  Second Life: Alice Resident entered chat range (5.2 m).\n",
+        )
+        .map_err(crate::Error::TailerIoError)?;
+        let (_, processed) = reconstruct(
+            &path,
+            "Me Myself",
+            None,
+            None,
+            crate::config::Greetings::default(),
+        )?;
+        fs_err::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| {
+                std::io::Write::write_all(
+                    &mut file,
+                    b"[2024/05/01 20:01]  Second Life: Bob Builder entered chat range (12.0 m).\n",
+                )
+            })
+            .map_err(crate::Error::TailerIoError)?;
+        fs_err::rename(&path, temp_dir.path().join("chat-oldfile.txt"))
+            .map_err(crate::Error::TailerIoError)?;
+        fs_err::write(&path, "").map_err(crate::Error::TailerIoError)?;
+        let (presence, position) = reconstruct(
+            &path,
+            "Me Myself",
+            Some(processed),
+            None,
+            crate::config::Greetings::default(),
+        )?;
+        assert_eq!(
+            presence
+                .ungreeted()
+                .map(|avatar| avatar.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Alice Resident"],
+            "the old file should be replayed up to the processed position"
+        );
+        assert_eq!(position, processed);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_grace_periods() {
+        let mut presence = Presence::default();
+        presence.set_grace_periods(GracePeriods {
+            leave: std::time::Duration::from_secs(30),
+            reentry: std::time::Duration::from_secs(60),
+        });
+        presence.entered("Foo Bar", None, None);
+        presence.greeted("foo");
+        assert_eq!(presence.left("Foo Bar"), None, "leaving is not immediate");
+        tokio::time::advance(std::time::Duration::from_secs(10)).await;
+        presence.entered("Foo Bar", None, None);
+        assert!(
+            presence
+                .get("foo bar")
+                .is_some_and(|avatar| avatar.returned && avatar.greeted),
+            "returning while leaving continues the visit"
+        );
+        assert_eq!(presence.next_expiry(), None);
+        presence.left("Foo Bar");
+        tokio::time::advance(std::time::Duration::from_secs(29)).await;
+        assert!(presence.expire().is_empty(), "expired too early");
+        tokio::time::advance(std::time::Duration::from_secs(1)).await;
+        assert_eq!(
+            presence
+                .expire()
+                .into_iter()
+                .map(|avatar| avatar.name)
+                .collect::<Vec<_>>(),
+            ["Foo Bar"]
+        );
+        assert_eq!(presence.get("foo bar"), None);
+        tokio::time::advance(std::time::Duration::from_secs(59)).await;
+        presence.entered("Foo Bar", None, None);
+        assert!(
+            presence
+                .get("foo bar")
+                .is_some_and(|avatar| avatar.returned && avatar.greeted),
+            "returning within the re-entry grace period continues the visit"
+        );
+        presence.left("Foo Bar");
+        tokio::time::advance(std::time::Duration::from_secs(30 + 60)).await;
+        presence.expire();
+        presence.entered("Foo Bar", None, None);
+        assert!(
+            presence
+                .get("foo bar")
+                .is_some_and(|avatar| !avatar.returned && !avatar.greeted),
+            "returning after the grace periods starts a new visit"
+        );
+    }
+
+    #[test]
+    fn test_greeted_everyone() {
+        let mut presence = Presence::default();
+        presence.set_greetings(crate::config::Greetings {
+            late_arrivals: std::time::Duration::from_secs(5),
+            ..crate::config::Greetings::default()
+        });
+        let greeted_at = time::macros::datetime!(2024-05-01 20:01:00);
+        presence.entered(
+            "Foo Bar",
+            Some(time::macros::datetime!(2024-05-01 20:00:00)),
+            None,
+        );
+        presence.entered("Baz Qux", None, None);
+        presence.entered(
+            "Quux Resident",
+            Some(time::macros::datetime!(2024-05-01 20:00:58)),
+            None,
+        );
+        assert!(
+            presence.is_late_arrival("Quux Resident", Some(greeted_at)),
+            "entering two seconds before the greeting is late"
+        );
+        presence.greeted_everyone(Some(greeted_at));
+        assert_eq!(
+            presence
+                .ungreeted()
+                .map(|avatar| avatar.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Quux Resident"],
+            "the late arrival still needs a greeting"
+        );
+    }
+
+    #[test]
+    fn test_starts_session() -> Result<(), crate::Error> {
+        let parse = |line: &str| {
+            sl_chat_log_parser::chat_log_line_parser()
+                .parse(line)
+                .into_result()
+                .map_err(|errors| {
+                    crate::Error::ChatLogLineParseError(crate::ChumskyError {
+                        description: "chat log line".to_string(),
+                        source: line.to_string(),
+                        errors: errors.into_iter().map(|e| e.into_owned()).collect(),
+                    })
+                })
+        };
+        let previous = Some(time::macros::datetime!(2024-05-01 20:00:00));
+        let gap = Some(std::time::Duration::from_secs(60 * 60));
+        let chat = parse("[2024/05/01 20:30]  Foo Bar: hello")?;
+        assert!(
+            !starts_session(&chat, previous, gap),
+            "a line within the gap does not start a session"
+        );
+        let late_chat = parse("[2024/05/01 21:30]  Foo Bar: hello")?;
+        assert!(
+            starts_session(&late_chat, previous, gap),
+            "a line after the gap starts a session"
+        );
+        assert!(
+            !starts_session(&late_chat, previous, None),
+            "gaps are ignored without an idle gap"
+        );
+        let login = parse("[2024/05/01 20:30]  Second Life: Logging in...")?;
+        assert!(
+            starts_session(&login, previous, gap),
+            "the login messages start a session"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,531 @@
+//! write-behind storage of our state in the redb database
+//!
+//! Busy events produce many last seen updates per second and committing
+//! each of them in its own write transaction syncs the disk for every
+//! single line, so updates are collected and committed in batches instead.
+
+/// how durable the periodic commits of batched updates are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum CommitDurability {
+    /// every batch is synced to disk when it is committed
+    Immediate,
+    /// batches are only synced to disk on shutdown, a crash loses all
+    /// updates since the start of the program
+    None,
+}
+
+impl From<CommitDurability> for redb::Durability {
+    fn from(value: CommitDurability) -> Self {
+        match value {
+            CommitDurability::Immediate => Self::Immediate,
+            CommitDurability::None => Self::None,
+        }
+    }
+}
+
+/// when and where an avatar was last seen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LastSeen {
+    /// the time the avatar was last seen
+    pub(crate) timestamp: time::PrimitiveDateTime,
+    /// the region the avatar was last seen in, if known
+    pub(crate) region: Option<String>,
+}
+
+/// an update to the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DbUpdate {
+    /// an avatar was seen at the given time
+    LastSeen {
+        /// the lower case legacy name of the avatar
+        name: String,
+        /// the time the avatar was seen
+        timestamp: time::PrimitiveDateTime,
+        /// the region the avatar was seen in, if known
+        region: Option<String>,
+    },
+    /// we found out in which region an avatar was last seen
+    LastSeenRegion {
+        /// the lower case legacy name of the avatar
+        name: String,
+        /// the region the avatar was last seen in
+        region: String,
+    },
+    /// a tailed log file was processed up to the given position
+    TailPosition {
+        /// the file name of the log file
+        file: String,
+        /// the position up to which it was processed
+        position: crate::tailer::TailPosition,
+    },
+    /// a hello notification was shown or closed
+    PendingNotification {
+        /// the lower case legacy name of the avatar the notification is about
+        name: String,
+        /// the notification or None if it was closed
+        notification: Option<crate::notifications::StoredNotification>,
+    },
+}
+
+/// updates which were not committed yet, later updates to the same key
+/// replace earlier ones
+#[derive(Debug, Default)]
+struct PendingUpdates {
+    /// last seen time by lower case avatar name
+    last_seen: std::collections::BTreeMap<String, time::PrimitiveDateTime>,
+    /// known (Some) or unknown (None) last seen region by lower case
+    /// avatar name
+    last_seen_regions: std::collections::BTreeMap<String, Option<String>>,
+    /// processed position by log file name
+    tail_positions: std::collections::BTreeMap<String, crate::tailer::TailPosition>,
+    /// shown (Some) or closed (None) notifications by lower case avatar name
+    pending_notifications:
+        std::collections::BTreeMap<String, Option<crate::notifications::StoredNotification>>,
+}
+
+impl PendingUpdates {
+    /// add an update to the batch
+    fn add(&mut self, update: DbUpdate) {
+        match update {
+            DbUpdate::LastSeen {
+                name,
+                timestamp,
+                region,
+            } => {
+                self.last_seen_regions.insert(name.clone(), region);
+                self.last_seen.insert(name, timestamp);
+            }
+            DbUpdate::LastSeenRegion { name, region } => {
+                self.last_seen_regions.insert(name, Some(region));
+            }
+            DbUpdate::TailPosition { file, position } => {
+                self.tail_positions.insert(file, position);
+            }
+            DbUpdate::PendingNotification { name, notification } => {
+                self.pending_notifications.insert(name, notification);
+            }
+        }
+    }
+
+    /// are there any updates waiting to be committed
+    fn is_empty(&self) -> bool {
+        self.last_seen.is_empty()
+            && self.last_seen_regions.is_empty()
+            && self.tail_positions.is_empty()
+            && self.pending_notifications.is_empty()
+    }
+
+    /// commit all pending updates in a single write transaction
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the write transaction fails
+    fn commit(
+        &mut self,
+        db: &redb::Database,
+        durability: redb::Durability,
+    ) -> Result<(), crate::Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let mut write_txn = db.begin_write()?;
+        write_txn.set_durability(durability)?;
+        {
+            let mut table = write_txn.open_table(crate::LAST_SEEN_TABLE)?;
+            for (name, timestamp) in &self.last_seen {
+                table.insert(name.to_owned(), &timestamp.format(crate::TIME_FORMAT)?)?;
+            }
+            let mut table = write_txn.open_table(crate::LAST_SEEN_REGION_TABLE)?;
+            for (name, region) in &self.last_seen_regions {
+                if let Some(region) = region {
+                    table.insert(name.to_owned(), region.to_owned())?;
+                } else {
+                    table.remove(name.to_owned())?;
+                }
+            }
+            let mut table = write_txn.open_table(crate::TAIL_POSITION_TABLE)?;
+            for (file, position) in &self.tail_positions {
+                table.insert(
+                    file.to_owned(),
+                    (
+                        position
+                            .identity
+                            .map(|identity| (identity.device, identity.inode)),
+                        position.offset,
+                    ),
+                )?;
+            }
+            let mut table = write_txn.open_table(crate::PENDING_NOTIFICATION_TABLE)?;
+            for (name, notification) in &self.pending_notifications {
+                if let Some(notification) = notification {
+                    table.insert(
+                        name.to_owned(),
+                        (
+                            notification.id,
+                            notification.summary.to_owned(),
+                            notification.body.to_owned(),
+                        ),
+                    )?;
+                } else {
+                    table.remove(name.to_owned())?;
+                }
+            }
+        }
+        write_txn.commit()?;
+        tracing::debug!(
+            "Committed {} last seen updates, {} log positions and {} notification updates",
+            self.last_seen.len(),
+            self.tail_positions.len(),
+            self.pending_notifications.len()
+        );
+        self.last_seen.clear();
+        self.last_seen_regions.clear();
+        self.tail_positions.clear();
+        self.pending_notifications.clear();
+        Ok(())
+    }
+}
+
+/// handle to send updates to the database writer task
+#[derive(Debug, Clone)]
+pub(crate) struct DbWriter {
+    /// sends updates to the writer task
+    tx: tokio::sync::mpsc::Sender<DbUpdate>,
+}
+
+impl DbWriter {
+    /// spawn the writer task
+    ///
+    /// updates are committed `commit_interval` after the first update of
+    /// a batch, all remaining updates are committed and synced to disk
+    /// once every handle is dropped, the task is idle while there are no
+    /// updates
+    pub(crate) fn spawn(
+        db: std::sync::Arc<redb::Database>,
+        commit_interval: std::time::Duration,
+        durability: CommitDurability,
+    ) -> (Self, tokio::task::JoinHandle<Result<(), crate::Error>>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(crate::CHANNEL_CAPACITY);
+        let join_handle = tokio::spawn(write_behind(db, rx, commit_interval, durability.into()));
+        (Self { tx }, join_handle)
+    }
+
+    /// record the time and, if known, the region an avatar was last seen
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the writer task stopped, usually because of a
+    /// database error which the task logged before stopping
+    pub(crate) async fn last_seen(
+        &self,
+        name: &str,
+        timestamp: time::PrimitiveDateTime,
+        region: Option<&str>,
+    ) -> Result<(), crate::Error> {
+        self.tx
+            .send(DbUpdate::LastSeen {
+                name: name.to_lowercase(),
+                timestamp,
+                region: region.map(ToOwned::to_owned),
+            })
+            .await
+            .map_err(|_err| crate::Error::DbWriterStopped)
+    }
+
+    /// record the region an avatar was last seen in once we found out
+    /// which region that was
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the writer task stopped, usually because of a
+    /// database error which the task logged before stopping
+    pub(crate) async fn last_seen_region(
+        &self,
+        name: &str,
+        region: &str,
+    ) -> Result<(), crate::Error> {
+        self.tx
+            .send(DbUpdate::LastSeenRegion {
+                name: name.to_lowercase(),
+                region: region.to_owned(),
+            })
+            .await
+            .map_err(|_err| crate::Error::DbWriterStopped)
+    }
+
+    /// record the position up to which a log file has been processed
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the writer task stopped, usually because of a
+    /// database error which the task logged before stopping
+    pub(crate) async fn tail_position(
+        &self,
+        file: &str,
+        position: crate::tailer::TailPosition,
+    ) -> Result<(), crate::Error> {
+        self.tx
+            .send(DbUpdate::TailPosition {
+                file: file.to_owned(),
+                position,
+            })
+            .await
+            .map_err(|_err| crate::Error::DbWriterStopped)
+    }
+
+    /// record that a hello notification was shown (Some) or closed (None)
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the writer task stopped, usually because of a
+    /// database error which the task logged before stopping
+    pub(crate) async fn pending_notification(
+        &self,
+        name: &str,
+        notification: Option<crate::notifications::StoredNotification>,
+    ) -> Result<(), crate::Error> {
+        self.tx
+            .send(DbUpdate::PendingNotification {
+                name: name.to_lowercase(),
+                notification,
+            })
+            .await
+            .map_err(|_err| crate::Error::DbWriterStopped)
+    }
+}
+
+/// read when and where every avatar was last seen by lower case name
+///
+/// # Errors
+///
+/// returns an error if reading from the database fails
+pub(crate) fn read_last_seen(
+    db: &redb::Database,
+) -> Result<std::collections::BTreeMap<String, LastSeen>, crate::Error> {
+    let read_txn = redb::ReadableDatabase::begin_read(db)?;
+    let mut last_seen = std::collections::BTreeMap::new();
+    let Ok(table) = read_txn.open_table(crate::LAST_SEEN_TABLE) else {
+        return Ok(last_seen);
+    };
+    let regions = read_txn.open_table(crate::LAST_SEEN_REGION_TABLE).ok();
+    for item in redb::ReadableTable::iter(&table)? {
+        let (key, value) = item?;
+        let name = key.value();
+        let timestamp = time::PrimitiveDateTime::parse(&value.value(), &crate::TIME_FORMAT)?;
+        let region = if let Some(regions) = &regions {
+            regions.get(name.to_owned())?.map(|value| value.value())
+        } else {
+            None
+        };
+        last_seen.insert(name, LastSeen { timestamp, region });
+    }
+    Ok(last_seen)
+}
+
+/// read the hello notifications a previous run left on screen
+///
+/// # Errors
+///
+/// returns an error if reading from the database fails
+pub(crate) fn read_pending_notifications(
+    db: &redb::Database,
+) -> Result<Vec<(String, crate::notifications::StoredNotification)>, crate::Error> {
+    let read_txn = redb::ReadableDatabase::begin_read(db)?;
+    let Ok(table) = read_txn.open_table(crate::PENDING_NOTIFICATION_TABLE) else {
+        return Ok(Vec::new());
+    };
+    let mut notifications = Vec::new();
+    for item in redb::ReadableTable::iter(&table)? {
+        let (key, value) = item?;
+        let (id, summary, body) = value.value();
+        notifications.push((
+            key.value(),
+            crate::notifications::StoredNotification { id, summary, body },
+        ));
+    }
+    Ok(notifications)
+}
+
+/// read the position up to which a log file was processed by a previous run
+///
+/// # Errors
+///
+/// returns an error if reading from the database fails
+pub(crate) fn read_tail_position(
+    db: &redb::Database,
+    file: &str,
+) -> Result<Option<crate::tailer::TailPosition>, crate::Error> {
+    let read_txn = redb::ReadableDatabase::begin_read(db)?;
+    let Ok(table) = read_txn.open_table(crate::TAIL_POSITION_TABLE) else {
+        return Ok(None);
+    };
+    Ok(table.get(file.to_owned())?.map(|value| {
+        let (identity, offset) = value.value();
+        crate::tailer::TailPosition {
+            identity: identity.map(|(device, inode)| crate::tailer::FileIdentity { device, inode }),
+            offset,
+        }
+    }))
+}
+
+/// the database writer task, errors are also logged right away since the
+/// senders only see that the task stopped and it may not be joined before
+/// they give up
+///
+/// # Errors
+///
+/// returns an error if committing to the database fails
+async fn write_behind(
+    db: std::sync::Arc<redb::Database>,
+    rx: tokio::sync::mpsc::Receiver<DbUpdate>,
+    commit_interval: std::time::Duration,
+    durability: redb::Durability,
+) -> Result<(), crate::Error> {
+    let result = commit_updates(db, rx, commit_interval, durability).await;
+    if let Err(err) = &result {
+        tracing::error!("Database writer stopped: {err}");
+    }
+    result
+}
+
+/// commit the updates received until every sender is gone
+///
+/// # Errors
+///
+/// returns an error if committing to the database fails
+async fn commit_updates(
+    db: std::sync::Arc<redb::Database>,
+    mut rx: tokio::sync::mpsc::Receiver<DbUpdate>,
+    commit_interval: std::time::Duration,
+    durability: redb::Durability,
+) -> Result<(), crate::Error> {
+    let mut pending = PendingUpdates::default();
+    let mut deadline = tokio::time::Instant::now();
+    loop {
+        let update = if pending.is_empty() {
+            rx.recv().await
+        } else if let Ok(update) = tokio::time::timeout_at(deadline, rx.recv()).await {
+            update
+        } else {
+            pending.commit(&db, durability)?;
+            continue;
+        };
+        let Some(update) = update else {
+            break;
+        };
+        if pending.is_empty() {
+            deadline = tokio::time::Instant::now() + commit_interval;
+        }
+        pending.add(update);
+        if commit_interval.is_zero() {
+            pending.commit(&db, durability)?;
+        }
+    }
+    pending.commit(&db, redb::Durability::Immediate)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use redb::{ReadableDatabase as _, ReadableTableMetadata as _};
+
+    #[tokio::test]
+    async fn test_db_writer_coalesces_and_flushes_on_shutdown() -> Result<(), crate::Error> {
+        let test_db = crate::test_util::TestDb::new()?;
+        let (writer, join_handle) = test_db.writer(
+            std::time::Duration::from_secs(60 * 60),
+            CommitDurability::None,
+        );
+        writer
+            .last_seen(
+                "Foo Bar",
+                time::macros::datetime!(2024-05-01 20:00:00),
+                Some("Da Boom"),
+            )
+            .await?;
+        writer
+            .last_seen(
+                "foo bar",
+                time::macros::datetime!(2024-05-01 20:05:00),
+                None,
+            )
+            .await?;
+        writer.last_seen_region("Foo Bar", "Ahern").await?;
+        drop(writer);
+        join_handle.await??;
+        let read_txn = test_db.db.begin_read()?;
+        let table = read_txn.open_table(crate::LAST_SEEN_TABLE)?;
+        assert_eq!(
+            table.len()?,
+            1,
+            "updates for the same avatar should be coalesced"
+        );
+        assert_eq!(
+            table.get("foo bar".to_string())?.map(|value| value.value()),
+            Some("2024-05-01 20:05:00".to_string())
+        );
+        let table = read_txn.open_table(crate::LAST_SEEN_REGION_TABLE)?;
+        assert_eq!(
+            table.get("foo bar".to_string())?.map(|value| value.value()),
+            Some("Ahern".to_string())
+        );
+        assert_eq!(
+            read_last_seen(&test_db.db)?.get("foo bar"),
+            Some(&LastSeen {
+                timestamp: time::macros::datetime!(2024-05-01 20:05:00),
+                region: Some("Ahern".to_string()),
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_db_writer_tail_position_roundtrip() -> Result<(), crate::Error> {
+        let test_db = crate::test_util::TestDb::new()?;
+        let (writer, join_handle) =
+            test_db.writer(std::time::Duration::ZERO, CommitDurability::Immediate);
+        let position = crate::tailer::TailPosition {
+            identity: Some(crate::tailer::FileIdentity {
+                device: 1,
+                inode: 42,
+            }),
+            offset: 1234,
+        };
+        writer.tail_position("chat.txt", position).await?;
+        drop(writer);
+        join_handle.await??;
+        assert_eq!(read_tail_position(&test_db.db, "chat.txt")?, Some(position));
+        assert_eq!(read_tail_position(&test_db.db, "other.txt")?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_db_writer_pending_notifications() -> Result<(), crate::Error> {
+        let test_db = crate::test_util::TestDb::new()?;
+        let (writer, join_handle) = test_db.writer(
+            std::time::Duration::from_secs(60),
+            CommitDurability::Immediate,
+        );
+        let notification = |id| crate::notifications::StoredNotification {
+            id,
+            summary: "New person entered chat range".to_string(),
+            body: "Foo Bar entered the chat range".to_string(),
+        };
+        writer
+            .pending_notification("foo bar", Some(notification(1)))
+            .await?;
+        writer
+            .pending_notification("Baz Qux", Some(notification(2)))
+            .await?;
+        writer.pending_notification("baz qux", None).await?;
+        drop(writer);
+        join_handle.await??;
+        assert_eq!(
+            read_pending_notifications(&test_db.db)?,
+            [("foo bar".to_string(), notification(1))]
+        );
+        Ok(())
+    }
+}
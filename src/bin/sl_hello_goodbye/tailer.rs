@@ -0,0 +1,568 @@
+//! follows a log file across truncation, rotation and invalid UTF-8
+//!
+//! Firestorm renames `chat.txt` to `chat-oldfile.txt` at login and starts
+//! a new file, and old logs occasionally contain bytes that are not valid
+//! UTF-8, so a plain line reader on a single file handle is not enough.
+
+use std::io::{Read as _, Seek as _};
+use std::path::{Path, PathBuf};
+
+/// the delay before the first attempt to restart tailing after an error
+const MIN_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// the maximum delay between attempts to restart tailing after errors
+const MAX_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// identifies a file independent of its name so we can tell when the viewer
+/// rotated the log (renamed the old one and created a new one)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileIdentity {
+    /// the device the file lives on
+    pub(crate) device: u64,
+    /// the inode of the file on that device
+    pub(crate) inode: u64,
+}
+
+impl FileIdentity {
+    /// determine the identity of a file from its metadata
+    ///
+    /// returns None on platforms where we have no stable file identity,
+    /// rotation is then only detected when the new file is shorter than
+    /// the old one
+    #[cfg(unix)]
+    fn from_metadata(metadata: &std::fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt as _;
+        Some(Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+
+    /// determine the identity of a file from its metadata
+    ///
+    /// returns None on platforms where we have no stable file identity,
+    /// rotation is then only detected when the new file is shorter than
+    /// the old one
+    #[cfg(not(unix))]
+    const fn from_metadata(_metadata: &std::fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+/// a position in a particular file up to which it has been read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TailPosition {
+    /// the identity of the file
+    pub(crate) identity: Option<FileIdentity>,
+    /// the byte offset in the file
+    pub(crate) offset: u64,
+}
+
+/// something that happened to the tailed file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TailEvent {
+    /// a complete line was appended to the file
+    Line {
+        /// the line without the line terminator
+        line: String,
+        /// the position right after the line
+        end: TailPosition,
+    },
+    /// everything that was in the file when we started has been read,
+    /// all further lines are live
+    CaughtUp,
+    /// the file was truncated and is now read again from the start
+    Truncated,
+    /// the file was replaced by a new one which is read from the start
+    Rotated,
+}
+
+/// follows a single file by name, reading whatever gets appended to it
+#[derive(Debug)]
+pub(crate) struct Tailer {
+    /// the path of the file we follow
+    path: PathBuf,
+    /// the currently open file, if any
+    file: Option<fs_err::File>,
+    /// the identity of the currently open file
+    identity: Option<FileIdentity>,
+    /// the offset in the currently open file up to which we have read
+    offset: u64,
+    /// bytes read after the last line terminator
+    partial: Vec<u8>,
+}
+
+impl Tailer {
+    /// open the file at the given path and start following it from its
+    /// current end
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the file can not be opened
+    pub(crate) fn open_at_end(path: &Path) -> Result<Self, crate::Error> {
+        let mut file = fs_err::File::open(path).map_err(crate::Error::TailerIoError)?;
+        let identity =
+            FileIdentity::from_metadata(&file.metadata().map_err(crate::Error::TailerIoError)?);
+        let offset = file
+            .seek(std::io::SeekFrom::End(0))
+            .map_err(crate::Error::TailerIoError)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Some(file),
+            identity,
+            offset,
+            partial: Vec::new(),
+        })
+    }
+
+    /// open the file at the given path and start following it from its start
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the file can not be opened
+    pub(crate) fn open_at_start(path: &Path) -> Result<Self, crate::Error> {
+        let file = fs_err::File::open(path).map_err(crate::Error::TailerIoError)?;
+        let identity =
+            FileIdentity::from_metadata(&file.metadata().map_err(crate::Error::TailerIoError)?);
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Some(file),
+            identity,
+            offset: 0,
+            partial: Vec::new(),
+        })
+    }
+
+    /// open the file at the given path and start following it from a
+    /// previously read position
+    ///
+    /// if the viewer rotated the file since then the rest of the old file is
+    /// read first, followed by [`TailEvent::Rotated`] and the new file. If
+    /// the file was replaced otherwise or truncated it is read from the start
+    /// instead
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the file can not be opened
+    pub(crate) fn open_at(path: &Path, position: TailPosition) -> Result<Self, crate::Error> {
+        let holding_path = file_holding(path, position);
+        if holding_path != path {
+            tracing::info!(
+                "{} was rotated since it was last read, catching up on {} first",
+                path.display(),
+                holding_path.display()
+            );
+        }
+        let file = fs_err::File::open(&holding_path).map_err(crate::Error::TailerIoError)?;
+        let metadata = file.metadata().map_err(crate::Error::TailerIoError)?;
+        let identity = FileIdentity::from_metadata(&metadata);
+        let offset = if identity == position.identity && position.offset <= metadata.len() {
+            position.offset
+        } else {
+            tracing::info!(
+                "{} was replaced since it was last read, reading it from the start",
+                path.display()
+            );
+            0
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Some(file),
+            identity,
+            offset,
+            partial: Vec::new(),
+        })
+    }
+
+    /// check the file for new lines, truncation and rotation
+    ///
+    /// # Errors
+    ///
+    /// returns an error if reading the file or its metadata fails
+    pub(crate) fn poll(&mut self) -> Result<Vec<TailEvent>, crate::Error> {
+        let mut events = Vec::new();
+        // on rotation this drains whatever was written to the old file
+        // before the new one was created
+        self.read_available(&mut events)?;
+        let metadata = match fs_err::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                // between the rename and the creation of the new file
+                return Ok(events);
+            }
+            Err(err) => return Err(crate::Error::TailerIoError(err)),
+        };
+        let identity = FileIdentity::from_metadata(&metadata);
+        if self.file.is_none() || (identity.is_some() && identity != self.identity) {
+            tracing::info!("{} was rotated, reading new file", self.path.display());
+            self.flush_partial(&mut events);
+            self.file = Some(fs_err::File::open(&self.path).map_err(crate::Error::TailerIoError)?);
+            self.identity = identity;
+            self.offset = 0;
+            events.push(TailEvent::Rotated);
+            self.read_available(&mut events)?;
+        } else if metadata.len() < self.offset {
+            tracing::info!("{} was truncated, reading from start", self.path.display());
+            self.partial.clear();
+            self.offset = 0;
+            events.push(TailEvent::Truncated);
+            self.read_available(&mut events)?;
+        }
+        Ok(events)
+    }
+
+    /// read everything appended to the currently open file since the last
+    /// read and turn it into line events
+    ///
+    /// # Errors
+    ///
+    /// returns an error if reading the file fails
+    fn read_available(&mut self, events: &mut Vec<TailEvent>) -> Result<(), crate::Error> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.seek(std::io::SeekFrom::Start(self.offset))
+            .map_err(crate::Error::TailerIoError)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)
+            .map_err(crate::Error::TailerIoError)?;
+        self.offset = file
+            .stream_position()
+            .map_err(crate::Error::TailerIoError)?;
+        self.partial.extend_from_slice(&buffer);
+        while let Some(position) = self.partial.iter().position(|b| *b == b'\n') {
+            let rest = self.partial.split_off(position + 1);
+            let mut line = std::mem::replace(&mut self.partial, rest);
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            events.push(TailEvent::Line {
+                line: String::from_utf8_lossy(&line).into_owned(),
+                end: self.position(),
+            });
+        }
+        Ok(())
+    }
+
+    /// emit an unterminated last line of a file we are about to stop reading
+    fn flush_partial(&mut self, events: &mut Vec<TailEvent>) {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            events.push(TailEvent::Line {
+                line: String::from_utf8_lossy(&line).into_owned(),
+                end: self.position(),
+            });
+        }
+    }
+
+    /// the position after the last complete line we read
+    pub(crate) fn position(&self) -> TailPosition {
+        TailPosition {
+            identity: self.identity,
+            offset: self
+                .offset
+                .saturating_sub(u64::try_from(self.partial.len()).unwrap_or(u64::MAX)),
+        }
+    }
+}
+
+/// the path Firestorm renames the log at the given path to when it starts a
+/// new one at login, `chat.txt` becomes `chat-oldfile.txt`
+fn old_file_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+    file_name.push("-oldfile");
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+/// the path of the file a previously read position is in, the given path
+/// or, if the viewer rotated that since, the old file it was renamed to
+///
+/// falls back to the given path if neither is the file of the position
+pub(crate) fn file_holding(path: &Path, position: TailPosition) -> PathBuf {
+    let identity_of = |path: &Path| {
+        fs_err::metadata(path)
+            .ok()
+            .and_then(|metadata| FileIdentity::from_metadata(&metadata))
+    };
+    if position.identity.is_none() || identity_of(path) == position.identity {
+        return path.to_path_buf();
+    }
+    let old_path = old_file_path(path);
+    if identity_of(&old_path) == position.identity {
+        old_path
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// watch the directory containing the tailed file and send all events
+/// for the file until the receiver goes away
+///
+/// # Errors
+///
+/// returns an error if watching or reading the file fails
+async fn follow(
+    tailer: &mut Tailer,
+    tx: &tokio::sync::mpsc::Sender<TailEvent>,
+) -> Result<(), crate::Error> {
+    let file_name = tailer.path.file_name().map(std::ffi::OsStr::to_os_string);
+    // the watcher only signals that the file needs to be checked, a single
+    // queued signal covers any number of changes so the channel never needs
+    // to hold more than one
+    let (watch_tx, mut watch_rx) = tokio::sync::mpsc::channel(1);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let signal = match event {
+            // our own reads would otherwise wake us up again
+            Ok(event) if matches!(event.kind, notify::EventKind::Access(_)) => return,
+            Ok(event)
+                if !event.paths.iter().any(|path| {
+                    path.file_name().map(std::ffi::OsStr::to_os_string) == file_name
+                }) =>
+            {
+                return;
+            }
+            Ok(_) => Ok(()),
+            Err(err) => Err(err),
+        };
+        match watch_tx.try_send(signal) {
+            Ok(()) | Err(tokio::sync::mpsc::error::TrySendError::Full(Ok(()))) => {}
+            Err(tokio::sync::mpsc::error::TrySendError::Full(signal)) => {
+                if let Err(err) = watch_tx.blocking_send(signal) {
+                    tracing::debug!("File watch signal receiver is gone: {:?}", err);
+                }
+            }
+            Err(tokio::sync::mpsc::error::TrySendError::Closed(signal)) => {
+                tracing::debug!("File watch signal receiver is gone: {:?}", signal);
+            }
+        }
+    })?;
+    let directory = tailer
+        .path
+        .parent()
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    notify::Watcher::watch(
+        &mut watcher,
+        &directory,
+        notify::RecursiveMode::NonRecursive,
+    )?;
+    loop {
+        // the first iteration catches up on anything written while we were
+        // not watching yet
+        for event in tailer.poll()? {
+            if tx.send(event).await.is_err() {
+                return Ok(());
+            }
+        }
+        let Some(signal) = watch_rx.recv().await else {
+            return Err(crate::Error::FileWatcherClosed);
+        };
+        signal?;
+    }
+}
+
+/// tail the file at the given path from the given position or its current
+/// end, sending every event to the given channel until the receiver goes
+/// away
+///
+/// everything between the given position and the current end of the file
+/// is sent before [`TailEvent::CaughtUp`], including the rest of the old
+/// file if the viewer rotated it in the meantime
+///
+/// errors are logged and tailing is restarted after a delay instead of
+/// giving up, lines that were already read are not sent again
+///
+/// # Errors
+///
+/// returns an error if the file can not be opened or read initially
+pub(crate) async fn tail(
+    path: PathBuf,
+    start: Option<TailPosition>,
+    tx: tokio::sync::mpsc::Sender<TailEvent>,
+) -> Result<(), crate::Error> {
+    let mut tailer = match start {
+        Some(position) => Tailer::open_at(&path, position)?,
+        None => Tailer::open_at_end(&path)?,
+    };
+    for event in tailer.poll()?.into_iter().chain([TailEvent::CaughtUp]) {
+        if tx.send(event).await.is_err() {
+            return Ok(());
+        }
+    }
+    let mut restart_delay = MIN_RESTART_DELAY;
+    loop {
+        let started = std::time::Instant::now();
+        match follow(&mut tailer, &tx).await {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                if started.elapsed() > MAX_RESTART_DELAY {
+                    restart_delay = MIN_RESTART_DELAY;
+                }
+                tracing::error!(
+                    "Error tailing {}, restarting in {}: {err}",
+                    path.display(),
+                    humantime::format_duration(restart_delay)
+                );
+                tokio::time::sleep(restart_delay).await;
+                restart_delay = (restart_delay * 2).min(MAX_RESTART_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Write as _;
+
+    /// create a file with an old line in a fresh temporary directory which
+    /// is deleted when the returned guard is dropped
+    fn temp_file() -> Result<(tempfile::TempDir, PathBuf), crate::Error> {
+        let dir = crate::test_util::temp_dir()?;
+        let path = dir.path().join("chat.txt");
+        fs_err::write(&path, b"old line\n").map_err(crate::Error::TailerIoError)?;
+        Ok((dir, path))
+    }
+
+    /// append bytes to a file
+    fn append(path: &Path, bytes: &[u8]) -> Result<(), crate::Error> {
+        fs_err::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(bytes))
+            .map_err(crate::Error::TailerIoError)
+    }
+
+    /// the line of a line event
+    fn line_of(event: TailEvent) -> Option<String> {
+        if let TailEvent::Line { line, .. } = event {
+            Some(line)
+        } else {
+            None
+        }
+    }
+
+    /// just the lines out of the events of a poll
+    fn lines(events: Vec<TailEvent>) -> Vec<Option<String>> {
+        events.into_iter().map(line_of).collect()
+    }
+
+    /// shorthand for an expected line
+    fn line(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn test_tailer_partial_lines_and_invalid_utf8() -> Result<(), crate::Error> {
+        let (_dir, path) = temp_file()?;
+        let mut tailer = Tailer::open_at_end(&path)?;
+        assert_eq!(tailer.poll()?, [], "existing content should be skipped");
+        append(&path, b"first\r\nsec")?;
+        assert_eq!(lines(tailer.poll()?), [line("first")]);
+        append(&path, b"ond \xff\n")?;
+        assert_eq!(lines(tailer.poll()?), [line("second \u{fffd}")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tailer_resume_from_position() -> Result<(), crate::Error> {
+        let (_dir, path) = temp_file()?;
+        let mut tailer = Tailer::open_at_end(&path)?;
+        append(&path, b"processed\nunterminated")?;
+        assert_eq!(lines(tailer.poll()?), [line("processed")]);
+        let end = tailer.position();
+        drop(tailer);
+        append(&path, b" line\nmissed\n")?;
+        let mut tailer = Tailer::open_at(&path, end)?;
+        assert_eq!(
+            lines(tailer.poll()?),
+            [line("unterminated line"), line("missed")]
+        );
+        let stale = TailPosition {
+            identity: None,
+            offset: end.offset,
+        };
+        if end.identity.is_some() {
+            let mut tailer = Tailer::open_at(&path, stale)?;
+            assert_eq!(
+                lines(tailer.poll()?),
+                [
+                    line("old line"),
+                    line("processed"),
+                    line("unterminated line"),
+                    line("missed")
+                ],
+                "a different file should be read from the start"
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tailer_resume_across_rotation() -> Result<(), crate::Error> {
+        let (_dir, path) = temp_file()?;
+        let mut tailer = Tailer::open_at_end(&path)?;
+        append(&path, b"processed\n")?;
+        assert_eq!(lines(tailer.poll()?), [line("processed")]);
+        let end = tailer.position();
+        drop(tailer);
+        append(&path, b"missed\n")?;
+        fs_err::rename(&path, path.with_file_name("chat-oldfile.txt"))
+            .map_err(crate::Error::TailerIoError)?;
+        fs_err::write(&path, b"first new line\n").map_err(crate::Error::TailerIoError)?;
+        assert_eq!(
+            file_holding(&path, end),
+            path.with_file_name("chat-oldfile.txt")
+        );
+        let mut tailer = Tailer::open_at(&path, end)?;
+        let events = tailer.poll()?;
+        assert_eq!(events.get(1), Some(&TailEvent::Rotated));
+        assert_eq!(
+            lines(events),
+            [line("missed"), None, line("first new line")],
+            "the rest of the old file should be read before the new one"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tailer_truncation() -> Result<(), crate::Error> {
+        let (_dir, path) = temp_file()?;
+        let mut tailer = Tailer::open_at_end(&path)?;
+        fs_err::write(&path, b"new\n").map_err(crate::Error::TailerIoError)?;
+        let events = tailer.poll()?;
+        assert_eq!(events.first(), Some(&TailEvent::Truncated));
+        assert_eq!(lines(events), [None, line("new")]);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tailer_rotation() -> Result<(), crate::Error> {
+        let (_dir, path) = temp_file()?;
+        let mut tailer = Tailer::open_at_end(&path)?;
+        append(&path, b"last old line\nunterminated")?;
+        fs_err::rename(&path, path.with_file_name("chat-oldfile.txt"))
+            .map_err(crate::Error::TailerIoError)?;
+        fs_err::write(&path, b"first new line and then some more\n")
+            .map_err(crate::Error::TailerIoError)?;
+        let events = tailer.poll()?;
+        assert_eq!(events.get(2), Some(&TailEvent::Rotated));
+        assert_eq!(
+            lines(events),
+            [
+                line("last old line"),
+                line("unterminated"),
+                None,
+                line("first new line and then some more"),
+            ]
+        );
+        Ok(())
+    }
+}
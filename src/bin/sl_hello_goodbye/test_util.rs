@@ -0,0 +1,49 @@
+//! helpers shared by the tests of several modules
+
+/// a fresh temporary directory, it is deleted with everything in it when
+/// the returned guard is dropped
+///
+/// # Errors
+///
+/// returns an error if the directory can not be created
+pub(crate) fn temp_dir() -> Result<tempfile::TempDir, crate::Error> {
+    tempfile::Builder::new()
+        .prefix(&format!("{}-", clap::crate_name!()))
+        .tempdir()
+        .map_err(crate::Error::TailerIoError)
+}
+
+/// a database in a fresh temporary directory that is deleted when this is
+/// dropped
+#[derive(Debug)]
+pub(crate) struct TestDb {
+    /// the database
+    pub(crate) db: std::sync::Arc<redb::Database>,
+    /// the directory the database is in
+    _dir: tempfile::TempDir,
+}
+
+impl TestDb {
+    /// create an empty database
+    ///
+    /// # Errors
+    ///
+    /// returns an error if the directory or the database can not be created
+    pub(crate) fn new() -> Result<Self, crate::Error> {
+        let dir = temp_dir()?;
+        let db = std::sync::Arc::new(redb::Database::create(dir.path().join("last_seen.redb"))?);
+        Ok(Self { db, _dir: dir })
+    }
+
+    /// spawn a database writer task for the database
+    pub(crate) fn writer(
+        &self,
+        commit_interval: std::time::Duration,
+        durability: crate::store::CommitDurability,
+    ) -> (
+        crate::store::DbWriter,
+        tokio::task::JoinHandle<Result<(), crate::Error>>,
+    ) {
+        crate::store::DbWriter::spawn(std::sync::Arc::clone(&self.db), commit_interval, durability)
+    }
+}